@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::data::{Color, Coord};
+
+/// Compact wire format of a whole canvas, `GameInfo.canvas` is sent like this.
+///
+/// The canvas is a dense bitmap over its bounding box, row by row. Every pixel is an index into
+/// the palette, `0` being an empty pixel, and repeating indices are run-length encoded as
+/// `varint(run length) varint(index)` pairs.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncodedCanvas {
+    pub width: u16,
+    pub height: u16,
+    pub palette: Vec<Color>,
+    pub runs: Vec<u8>,
+}
+
+pub fn encode(canvas: &HashMap<Coord, Color>) -> EncodedCanvas {
+    let width = canvas.keys().map(|(x, _)| x + 1).max().unwrap_or(0);
+    let height = canvas.keys().map(|(_, y)| y + 1).max().unwrap_or(0);
+
+    let mut palette: Vec<Color> = Vec::new();
+    let mut indices: HashMap<Color, u64> = HashMap::new();
+    let mut runs = Vec::new();
+    let mut run: Option<(u64, u64)> = None; // index and length of the current run
+
+    let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)));
+    for point in pixels {
+        let index = canvas.get(&point).map_or(0, |color| {
+            *indices.entry(*color).or_insert_with(|| {
+                palette.push(*color);
+                palette.len() as u64
+            })
+        });
+
+        run = match run {
+            Some((current, len)) if current == index => Some((current, len + 1)),
+            Some((current, len)) => {
+                write_run(&mut runs, current, len);
+                Some((index, 1))
+            }
+            None => Some((index, 1)),
+        };
+    }
+
+    if let Some((index, len)) = run {
+        write_run(&mut runs, index, len);
+    }
+
+    EncodedCanvas {
+        width,
+        height,
+        palette,
+        runs,
+    }
+}
+
+pub fn decode(encoded: &EncodedCanvas) -> Result<HashMap<Coord, Color>, String> {
+    let (width, height) = (encoded.width as u64, encoded.height as u64);
+    let mut canvas = HashMap::new();
+    let mut bytes = encoded.runs.iter().copied();
+    let mut pos: u64 = 0;
+
+    while let Some(len) = read_varint(&mut bytes)? {
+        let index = read_varint(&mut bytes)?.ok_or("run without a color")?;
+
+        let end = pos
+            .checked_add(len)
+            .filter(|&end| end <= width * height)
+            .ok_or("runs don't fit the canvas")?;
+
+        if index != 0 {
+            let color = *encoded
+                .palette
+                .get(index as usize - 1)
+                .ok_or("color index out of palette")?;
+
+            for pixel in pos..end {
+                canvas.insert(((pixel % width) as u16, (pixel / width) as u16), color);
+            }
+        }
+
+        pos = end;
+    }
+
+    Ok(canvas)
+}
+
+fn write_run(runs: &mut Vec<u8>, index: u64, len: u64) {
+    write_varint(runs, len);
+    write_varint(runs, index);
+}
+
+/// LEB128, seven bits at a time with the high bit set on all but the last byte
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// next varint, none at the end of the bytes
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Result<Option<u64>, String> {
+    let mut value = 0;
+
+    for shift in (0..64).step_by(7) {
+        let byte = match bytes.next() {
+            Some(byte) => byte,
+            None if shift == 0 => return Ok(None),
+            None => return Err("truncated varint".to_owned()),
+        };
+
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    Err("varint too long".to_owned())
+}
+
+pub fn serialize<S: Serializer>(
+    canvas: &HashMap<Coord, Color>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    encode(canvas).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Coord, Color>, D::Error> {
+    let encoded = EncodedCanvas::deserialize(deserializer)?;
+    decode(&encoded).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(canvas: &HashMap<Coord, Color>) -> HashMap<Coord, Color> {
+        let bytes = bincode::serialize(&encode(canvas)).unwrap();
+        let encoded: EncodedCanvas = bincode::deserialize(&bytes).unwrap();
+        decode(&encoded).unwrap()
+    }
+
+    #[test]
+    fn empty_canvas() {
+        let canvas = HashMap::new();
+        assert_eq!(encode(&canvas), EncodedCanvas::default());
+        assert_eq!(round_trip(&canvas), canvas);
+    }
+
+    #[test]
+    fn single_pixel_in_the_corner() {
+        let canvas: HashMap<_, _> = vec![((119, 89), Color::Red)].into_iter().collect();
+        let encoded = encode(&canvas);
+
+        assert_eq!((encoded.width, encoded.height), (120, 90));
+        assert_eq!(round_trip(&canvas), canvas);
+    }
+
+    #[test]
+    fn mixed_colors() {
+        let canvas: HashMap<_, _> = (0..120u16)
+            .flat_map(|x| (0..90u16).map(move |y| (x, y)))
+            .filter(|(x, y)| (x * y) % 7 != 0)
+            .map(|(x, y)| {
+                let color = match (x + y) % 4 {
+                    0 => Color::Black,
+                    1 => Color::Indexed(((x * y) % 256) as u8),
+                    2 => Color::Rgb(x as u8, y as u8, 7),
+                    _ => Color::White,
+                };
+                ((x, y), color)
+            })
+            .collect();
+
+        assert_eq!(round_trip(&canvas), canvas);
+    }
+
+    #[test]
+    fn filled_canvas_is_small() {
+        let canvas: HashMap<_, _> = (0..120u16)
+            .flat_map(|x| (0..90u16).map(move |y| ((x, y), Color::Blue)))
+            .collect();
+        let encoded = encode(&canvas);
+
+        assert_eq!(encoded.palette, vec![Color::Blue]);
+        assert!(bincode::serialize(&encoded).unwrap().len() < 32);
+        assert_eq!(round_trip(&canvas), canvas);
+    }
+
+    #[test]
+    fn through_game_info_field() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper {
+            #[serde(with = "crate::canvas")]
+            canvas: HashMap<Coord, Color>,
+        }
+
+        let canvas: HashMap<_, _> = vec![((3, 4), Color::Green), ((70, 20), Color::Rgb(1, 2, 3))]
+            .into_iter()
+            .collect();
+        let bytes = bincode::serialize(&Wrapper {
+            canvas: canvas.clone(),
+        })
+        .unwrap();
+        let decoded: Wrapper = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(decoded.canvas, canvas);
+    }
+
+    #[test]
+    fn rejects_runs_past_the_canvas() {
+        let mut runs = Vec::new();
+        write_run(&mut runs, 1, 5);
+        let encoded = EncodedCanvas {
+            width: 2,
+            height: 2,
+            palette: vec![Color::Red],
+            runs,
+        };
+
+        assert!(decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_runs() {
+        let mut runs = Vec::new();
+        write_run(&mut runs, 0, 1);
+        write_run(&mut runs, 1, u64::MAX);
+        let encoded = EncodedCanvas {
+            width: 2,
+            height: 2,
+            palette: vec![Color::Red],
+            runs,
+        };
+
+        assert!(decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_colors() {
+        let mut runs = Vec::new();
+        write_run(&mut runs, 2, 1);
+        let encoded = EncodedCanvas {
+            width: 1,
+            height: 1,
+            palette: vec![Color::Red],
+            runs,
+        };
+
+        assert!(decode(&encoded).is_err());
+    }
+}
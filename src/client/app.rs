@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, time::Duration};
+use std::time::Duration;
 
 use crossterm::{
     event::{
@@ -13,27 +13,47 @@ use tui::Terminal;
 use crate::{
     data::Username,
     events::{EventQueue, EventSender},
-    message::ToClient,
+    export::{Drawing, Gallery},
+    message::{InitialRoomState, ProfileKey, ResumeToken, RoomRequest, ToClient, ToServer},
     utils::{self, AbortableTask},
 };
 
 use super::{
-    app_server::{AppServer, NetEvent},
+    app_server::{AppServer, ConnectionStatus, NetEvent, ServerAddr},
     error::Result,
-    ui::{self, Room, StartMenu, View},
+    replay::ReplayPlayer,
+    ui::{self, LeaderboardView, Room, StartMenu, View},
     InputEvent,
 };
 
+/// seconds between attempts to reconnect after the connection dropped
+const RECONNECT_INTERVAL_SECS: u64 = 2;
+
+/// number of attempts to reconnect before giving up, the server keeps our place for 30 seconds
+const MAX_RECONNECT_ATTEMPTS: usize = 12;
+
 pub enum Event {
     Input(InputEvent),
     Net(NetEvent),
+    Reconnect,
+    ReplayTick(u64),
     CtrlC,
     CloseNotification,
 }
 
+/// details needed to get back into a room after the connection dropped
+struct ResumeSession {
+    addr: ServerAddr,
+    key: String,
+    token: ResumeToken,
+    attempts: usize,
+}
+
 enum State {
     Start(StartMenu),
     InGameRoom(Box<Room>),
+    Leaderboard(Box<LeaderboardView>),
+    Replay(Box<ReplayPlayer>),
 }
 
 impl Default for State {
@@ -51,6 +71,11 @@ pub struct App {
     server: AppServer,
     should_exit: bool,
     notifications: Vec<String>,
+    resume_session: Option<ResumeSession>,
+    /// where drawings are saved with ctrl+s
+    export: Gallery,
+    /// profile our games count towards on servers that keep profiles
+    profile: Option<ProfileKey>,
 }
 
 impl App {
@@ -60,12 +85,38 @@ impl App {
 
     pub fn exit(&mut self) { self.should_exit = true; }
 
+    pub fn notifications(&self) -> &[String] { &self.notifications }
+
     pub fn set_name_input(&mut self, name: String) {
         if let State::Start(start_menu) = &mut self.state {
             start_menu.username_input.set_content(name);
         }
     }
 
+    pub fn set_export(&mut self, export: Gallery) { self.export = export; }
+
+    pub fn set_profile(&mut self, profile: ProfileKey) { self.profile = Some(profile); }
+
+    /// switch from the start menu to the leaderboard of the server we're connected to
+    pub fn show_leaderboard(&mut self) {
+        if !self.server.is_connected() {
+            return self.display_notif("Connect to a server first".to_owned());
+        }
+
+        if let State::Start(menu) = std::mem::take(&mut self.state) {
+            let view = LeaderboardView::new(menu);
+            self.server.send_message(view.request());
+            self.state = State::Leaderboard(Box::new(view));
+        }
+    }
+
+    pub fn save_drawing(&mut self, drawing: Drawing) {
+        match self.export.save(&drawing) {
+            Ok(path) => self.display_notif(format!("Saved drawing to {}", path.display())),
+            Err(e) => self.display_notif(format!("Could not save drawing: {}", e)),
+        }
+    }
+
     pub fn set_host_input(&mut self, addr: String) {
         if let State::Start(start_menu) = &mut self.state {
             start_menu.host_input.set_content(addr);
@@ -76,6 +127,8 @@ impl App {
         match &self.state {
             State::Start(start_menu) => start_menu,
             State::InGameRoom(room) => room.get_current_view(),
+            State::Leaderboard(view) => view.as_ref(),
+            State::Replay(player) => player.as_ref(),
         }
     }
 
@@ -83,10 +136,29 @@ impl App {
         match &mut self.state {
             State::Start(start_menu) => start_menu,
             State::InGameRoom(room) => room.get_current_view_mut(),
+            State::Leaderboard(view) => view.as_mut(),
+            State::Replay(player) => player.as_mut(),
         }
     }
 
-    pub fn connect_to_server(&mut self, addr: SocketAddr) {
+    /// watch a recorded game instead of connecting to a server
+    pub fn play_replay(&mut self, player: ReplayPlayer) {
+        self.state = State::Replay(Box::new(player));
+        self.schedule_replay_tick();
+    }
+
+    /// play the next entries of the replay when they are due
+    pub fn schedule_replay_tick(&mut self) {
+        if let State::Replay(player) = &mut self.state {
+            if let Some(delay) = player.until_next_entry() {
+                let tick = player.next_tick();
+                self.sender()
+                    .send_with_delay(Event::ReplayTick(tick), delay);
+            }
+        }
+    }
+
+    pub fn connect_to_server(&mut self, addr: ServerAddr) {
         self.server.connect(addr, self.event_queue.sender().clone());
     }
 
@@ -99,9 +171,17 @@ impl App {
 
     fn go_back(&mut self) {
         match &mut self.state {
+            State::Replay(_) => self.exit(),
+
+            State::Leaderboard(_) => {
+                if let State::Leaderboard(view) = std::mem::take(&mut self.state) {
+                    self.state = State::Start(view.into_menu());
+                }
+            }
+
             State::InGameRoom(_) => {
-                self.server
-                    .send_message(crate::message::ToServer::LeaveRoom);
+                self.resume_session = None;
+                self.server.send_message(ToServer::LeaveRoom);
             }
 
             State::Start(menu) => {
@@ -114,7 +194,7 @@ impl App {
         };
     }
 
-    fn display_notif(&mut self, error: String) {
+    pub fn display_notif(&mut self, error: String) {
         self.notifications.push(error);
         self.sender()
             .send_with_delay(Event::CloseNotification, Duration::from_secs(4));
@@ -125,25 +205,79 @@ impl App {
             NetEvent::SessionCreate(session) => {
                 self.server.set_session(session)?;
 
+                if let Some(profile) = self.profile {
+                    self.server.send_message(ToServer::Profile(profile));
+                }
+
                 let is_connected = self.server.is_connected();
-                if let State::Start(state) = &mut self.state {
-                    state.on_connection_status_changed(is_connected);
+                match &mut self.state {
+                    State::Start(state) => state.on_connection_status_changed(is_connected),
+
+                    // reconnected, ask to get our place in the room back
+                    State::InGameRoom(_) => {
+                        if let Some(resume) = &mut self.resume_session {
+                            resume.attempts = 0;
+
+                            let req = RoomRequest::Resume {
+                                key: resume.key.clone(),
+                                token: resume.token,
+                            };
+                            self.server.send_message(ToServer::RequestRoom(None, req));
+                        }
+                    }
+
+                    State::Leaderboard(_) | State::Replay(_) => {}
                 }
             }
 
             NetEvent::Status(status) => {
                 let addr = self.server.addr();
+                let connection_lost = matches!(
+                    status,
+                    ConnectionStatus::Dropped
+                        | ConnectionStatus::NotFound
+                        | ConnectionStatus::Timedout
+                );
                 self.server.set_status(status);
 
                 let is_connected = self.server.is_connected();
                 match &mut self.state {
-                    State::InGameRoom(ref room) => {
-                        if !is_connected {
+                    State::InGameRoom(ref room) if !is_connected => {
+                        let should_reconnect = self.resume_session.as_ref().is_some_and(|resume| {
+                            connection_lost && resume.attempts < MAX_RECONNECT_ATTEMPTS
+                        });
+
+                        if should_reconnect {
+                            let resume = self.resume_session.as_mut().unwrap();
+                            if resume.attempts == 0 {
+                                self.notifications
+                                    .push("Connection lost, reconnecting..".to_owned());
+                            }
+
+                            resume.attempts += 1;
+                            self.sender().send_with_delay(
+                                Event::Reconnect,
+                                Duration::from_secs(RECONNECT_INTERVAL_SECS),
+                            );
+                        } else {
+                            let resume = self.resume_session.take();
+                            let addr = addr.or_else(|| resume.map(|r| r.addr.to_string()));
+
                             self.state =
                                 State::Start(StartMenu::new(addr, Some(room.username.to_string())));
                         }
                     }
 
+                    State::InGameRoom(_) | State::Replay(_) => {}
+
+                    State::Leaderboard(_) => {
+                        if let State::Leaderboard(view) = std::mem::take(&mut self.state) {
+                            let mut menu = view.into_menu();
+                            menu.on_connection_status_changed(is_connected);
+                            self.state = State::Start(menu);
+                        }
+                    }
+
                     State::Start(ref mut start_menu) => {
                         start_menu.on_connection_status_changed(is_connected);
                     }
@@ -152,13 +286,21 @@ impl App {
 
             NetEvent::Message(message) => {
                 if let ToClient::Disconnect(reason) = *message {
-                    // if server disconnects us
+                    // if server disconnects us, there is no point in reconnecting
+                    self.resume_session = None;
                     self.display_notif(reason);
                 } else if let State::InGameRoom(room) = &mut self.state {
                     match *message {
                         ToClient::RoomEvent(event) => room.process_event(event),
+                        ToClient::JoinRoom(initial_room_state) => {
+                            // resumed our session after reconnecting
+                            self.set_resume_session(&initial_room_state);
+                            self.state = Room::new(initial_room_state).into();
+                            self.notifications.clear();
+                        }
                         ToClient::LeaveRoom(maybe_reason) => {
                             // kick to start screen
+                            self.resume_session = None;
                             self.state = State::Start(StartMenu::new(
                                 self.server.addr(),
                                 Some(room.username.to_string()),
@@ -177,10 +319,25 @@ impl App {
                             // ),
                         }
                     };
-                } else if let ToClient::JoinRoom(initial_room_state) = *message {
-                    self.state = Room::new(initial_room_state).into();
                 } else {
-                    unimplemented!("msg {:#?}", *message)
+                    match *message {
+                        ToClient::JoinRoom(initial_room_state) => {
+                            self.set_resume_session(&initial_room_state);
+                            self.state = Room::new(initial_room_state).into();
+                        }
+                        ToClient::Leaderboard(leaderboard) => {
+                            if let State::Leaderboard(view) = &mut self.state {
+                                view.set_leaderboard(leaderboard);
+                            }
+                        }
+                        // the room we tried to join or spectate doesn't exist
+                        ToClient::LeaveRoom(maybe_reason) => {
+                            if let Some(reason) = maybe_reason {
+                                self.display_notif(reason)
+                            }
+                        }
+                        message => log::warn!("unexpected message outside a room: {:?}", message),
+                    }
                 }
             }
         }
@@ -188,6 +345,29 @@ impl App {
         Ok(())
     }
 
+    fn set_resume_session(&mut self, initial_room_state: &InitialRoomState) {
+        let addr = self.server.server_addr().cloned();
+
+        self.resume_session = addr
+            .zip(initial_room_state.resume_token)
+            .map(|(addr, token)| ResumeSession {
+                addr,
+                key: initial_room_state.room.key.clone(),
+                token,
+                attempts: 0,
+            });
+    }
+
+    fn reconnect(&mut self) {
+        if let Some(addr) = self
+            .resume_session
+            .as_ref()
+            .map(|resume| resume.addr.clone())
+        {
+            self.connect_to_server(addr);
+        }
+    }
+
     fn handle_input_event(&mut self, event: InputEvent) -> Result<()> {
         if event == InputEvent::Key(KeyCode::Esc.into()) {
             self.go_back();
@@ -258,7 +438,6 @@ impl App {
 
         let input_task_handle = self.setup_input_events();
         let mut terminal = Terminal::new(ui::backend()).unwrap();
-        // TODO: display notifications if any.
 
         while !self.should_exit {
             terminal.draw(|frame| self.get_current_view().draw(frame, self))?;
@@ -275,6 +454,17 @@ impl App {
                     self.notifications.pop();
                 }
 
+                // try to get back into the room after losing connection
+                Event::Reconnect => self.reconnect(),
+
+                Event::ReplayTick(tick) => {
+                    if let State::Replay(player) = &mut self.state {
+                        if player.on_tick(tick).is_some() {
+                            self.schedule_replay_tick();
+                        }
+                    }
+                }
+
                 // close on ctrl-c
                 Event::CtrlC => self.exit(),
             }
@@ -1,32 +1,103 @@
-use std::{fmt::Debug, net::SocketAddr, time::Duration};
+use std::{
+    fmt::{self, Debug},
+    net::{SocketAddr, ToSocketAddrs},
+    time::{Duration, Instant},
+};
 
-use futures_util::{sink::Sink, FutureExt, SinkExt, StreamExt, TryFutureExt};
+use futures_util::{sink::Sink, SinkExt, StreamExt};
 use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::http::Uri;
 
 use crate::{
     encoding,
     events::{EventQueue, EventSender},
-    message,
-    utils::{self, AbortableTask},
+    message::{self, Handshake},
+    utils::{self, AbortableTask, MessageReader, MessageWriter, Transport},
+    websocket,
 };
 
+/// seconds to wait for the server to answer our handshake
+const HANDSHAKE_TIMEOUT_SECS: u64 = 5;
+
 use super::{
     app::Event,
     error::{Error, Result},
 };
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Default, Clone)]
 pub enum ConnectionStatus {
+    #[default]
     NotConnected,
     Connecting,
     Connected,
     NotFound,
     Dropped,
     Timedout,
+    InvalidServer,
+    Incompatible(String), // reason given in the handshake
+    Refused(String),      // reason the server gave for refusing the connection
+}
+
+impl fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionStatus::NotConnected => write!(f, "Not connected"),
+            ConnectionStatus::Connecting => write!(f, "Connecting.."),
+            ConnectionStatus::Connected => write!(f, "Connected"),
+            ConnectionStatus::NotFound => write!(f, "Server not found"),
+            ConnectionStatus::Dropped => write!(f, "Connection dropped"),
+            ConnectionStatus::Timedout => write!(f, "Connection timed out"),
+            ConnectionStatus::InvalidServer => write!(f, "Not a Termibbl server"),
+            ConnectionStatus::Incompatible(reason) | ConnectionStatus::Refused(reason) => {
+                write!(f, "{}", reason)
+            }
+        }
+    }
+}
+
+/// a server connection, a tcp stream or a websocket
+type Connection = Box<dyn Transport + Unpin>;
+
+/// where a server is reached, `ws://` urls are connected to over a websocket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerAddr {
+    Tcp(SocketAddr),
+    WebSocket { url: String, addr: SocketAddr },
+}
+
+impl ServerAddr {
+    /// parse and resolve the address of a server, a `host:port` or a `ws://host:port/path` url
+    pub fn resolve(host: &str) -> Option<ServerAddr> {
+        let host = host.trim();
+        let resolve = |host: &str, port: u16| (host, port).to_socket_addrs().ok()?.next();
+
+        match host.parse::<Uri>() {
+            Ok(uri) if uri.scheme_str() == Some("ws") => {
+                // ipv6 hosts keep their brackets in the url
+                let ip_or_name = uri.host()?.trim_start_matches('[').trim_end_matches(']');
+                let addr = resolve(ip_or_name, uri.port_u16().unwrap_or(80))?;
+
+                Some(ServerAddr::WebSocket {
+                    url: host.to_owned(),
+                    addr,
+                })
+            }
+            _ => host
+                .parse::<SocketAddr>()
+                .ok()
+                .or_else(|| host.to_socket_addrs().ok()?.next())
+                .map(ServerAddr::Tcp),
+        }
+    }
 }
 
-impl Default for ConnectionStatus {
-    fn default() -> Self { Self::NotConnected }
+impl fmt::Display for ServerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerAddr::Tcp(addr) => write!(f, "{}", addr),
+            ServerAddr::WebSocket { url, .. } => write!(f, "{}", url),
+        }
+    }
 }
 
 pub enum NetEvent {
@@ -36,7 +107,7 @@ pub enum NetEvent {
 }
 
 pub struct ServerSession {
-    server_addr: SocketAddr,
+    server_addr: ServerAddr,
     server_msg_tx: EventSender<message::ToServer>,
 }
 
@@ -56,7 +127,7 @@ impl AppServer {
         if self.connection_attempt_task.is_some() {
             ConnectionStatus::Connecting
         } else {
-            self.connection_status
+            self.connection_status.clone()
         }
     }
 
@@ -71,6 +142,10 @@ impl AppServer {
         self.session.as_ref().map(|s| s.server_addr.to_string())
     }
 
+    pub fn server_addr(&self) -> Option<&ServerAddr> {
+        self.session.as_ref().map(|s| &s.server_addr)
+    }
+
     pub fn set_status(&mut self, status: ConnectionStatus) {
         if !matches!(status, ConnectionStatus::Connected) {
             self.disconnect()
@@ -98,47 +173,82 @@ impl AppServer {
     }
 
     /// attempt to connect to termibbl server
-    pub fn connect(&mut self, server_addr: SocketAddr, app_tx: EventSender<Event>) {
+    pub fn connect(&mut self, server_addr: ServerAddr, app_tx: EventSender<Event>) {
         if self.is_connected() {
             self.disconnect();
         }
 
-        let handle = TcpStream::connect(server_addr)
-            .map_ok(|socket| {
-                // TODO: verify this is a Termibbl server and versions are compatible
-                utils::frame_socket(socket)
-            })
-            .map_err(Error::from)
-            .map(move |result| {
-                let net_event = match result {
-                    // create session to handle this socket and notify server
-                    Ok(socket) => NetEvent::SessionCreate(ServerSession::create(
-                        server_addr,
-                        app_tx.clone(),
-                        socket.0,
-                        socket.1,
-                    )),
-
-                    Err(err) => {
-                        let status = match err {
-                            Error::SendError(_) => ConnectionStatus::NotConnected,
-                            Error::IOError(err) => match err.kind() {
-                                std::io::ErrorKind::TimedOut => ConnectionStatus::Timedout,
-                                _ => ConnectionStatus::NotFound,
-                            },
-                            _ => unreachable!(),
-                        };
-
-                        NetEvent::Status(status)
-                    }
-                };
+        let handle = async move {
+            let net_event = match Self::open_connection(&server_addr).await {
+                // create session to handle this socket and notify server
+                Ok((reader, writer)) => NetEvent::SessionCreate(ServerSession::create(
+                    server_addr,
+                    app_tx.clone(),
+                    reader,
+                    writer,
+                )),
+
+                Err(err) => {
+                    let status = match err {
+                        Error::SendError(_) => ConnectionStatus::NotConnected,
+                        Error::IOError(err) => match err.kind() {
+                            std::io::ErrorKind::TimedOut => ConnectionStatus::Timedout,
+                            _ => ConnectionStatus::NotFound,
+                        },
+                        Error::HandshakeTimeout(_) => ConnectionStatus::Timedout,
+                        Error::EncodingError(_) | Error::WebSocket(_) | Error::InvalidServer => {
+                            ConnectionStatus::InvalidServer
+                        }
+                        Error::IncompatibleServer(reason) => ConnectionStatus::Incompatible(reason),
+                        Error::Refused(reason) => ConnectionStatus::Refused(reason),
+                        _ => unreachable!(),
+                    };
+
+                    NetEvent::Status(status)
+                }
+            };
 
-                app_tx.send(Event::Net(net_event));
-            });
+            app_tx.send(Event::Net(net_event));
+        };
 
         self.connection_attempt_task
             .replace(utils::dispatch_abortable_task(handle));
     }
+
+    /// connect to the server and verify it is a compatible Termibbl server
+    async fn open_connection(
+        server_addr: &ServerAddr,
+    ) -> Result<(
+        MessageReader<message::ToClient, Connection>,
+        MessageWriter<message::ToServer, Connection>,
+    )> {
+        let timeout = Duration::from_secs(HANDSHAKE_TIMEOUT_SECS);
+        let socket: Connection = match server_addr {
+            ServerAddr::Tcp(addr) => Box::new(TcpStream::connect(addr).await?),
+            ServerAddr::WebSocket { url, addr } => {
+                let stream = TcpStream::connect(addr).await?;
+                Box::new(tokio::time::timeout(timeout, websocket::connect(url, stream)).await??)
+            }
+        };
+        let (mut reader, mut writer) = utils::frame_socket(socket);
+
+        writer
+            .send(message::ToServer::Handshake(Handshake::default()))
+            .await?;
+
+        let handshake = match tokio::time::timeout(timeout, reader.next()).await? {
+            Some(Ok(message::ToClient::Handshake(handshake))) => handshake,
+            Some(Ok(message::ToClient::Disconnect(reason))) => return Err(Error::Refused(reason)),
+            Some(Err(err)) => return Err(err.into()),
+            _ => return Err(Error::InvalidServer),
+        };
+
+        if let Some(reason) = handshake.incompatibility() {
+            return Err(Error::IncompatibleServer(reason));
+        }
+
+        Ok((reader, writer))
+    }
 }
 
 impl Drop for ServerSession {
@@ -155,7 +265,7 @@ impl ServerSession {
         S: StreamExt<Item = encoding::Result<message::ToClient>> + Unpin + Send + 'static,
         W: Sink<message::ToServer> + Unpin + Send + 'static,
     >(
-        server_addr: SocketAddr,
+        server_addr: ServerAddr,
         app_tx: EventSender<Event>,
         mut server_to_client: S,
         mut client_to_server: W,
@@ -171,23 +281,66 @@ impl ServerSession {
             let mut heartbeat =
                 tokio::time::interval(Duration::from_secs(message::HEARTBEAT_INTERVAL));
 
-            let connection_status = loop {
+            // paints of a stroke are collected for a moment and sent as one
+            let batch_duration = Duration::from_millis(message::DRAW_BATCH_MILLIS);
+            let mut pending_paint: Option<message::Draw> = None;
+            let mut flush_paint_at = Instant::now();
+
+            let connection_status = 'connection: loop {
+                let flush_paint = tokio::time::delay_until(flush_paint_at.into());
+
                 tokio::select! {
                     _ = heartbeat.tick() => event_queue.sender().send(message::ToServer::Heartbeat),
 
+                    _ = flush_paint, if pending_paint.is_some() => {
+                        if let Some(draw) = pending_paint.take() {
+                            let msg = message::ToServer::Draw(draw);
+                            if client_to_server.send(msg).await.is_err() {
+                                break ConnectionStatus::Dropped;
+                            }
+                        }
+                    }
+
                     Some(to_server_msg) = event_queue.recv_async() => {
-                        if let message::ToServer::Disconnect = to_server_msg {
-                            let _ = client_to_server.send(to_server_msg).await;
-                            break ConnectionStatus::NotConnected;
-                        } else if client_to_server.send(to_server_msg).await.is_err() {
-                            break ConnectionStatus::Dropped;
+                        // anything else than a paint goes out right after the pending paint
+                        let mut to_send = Vec::new();
+                        match to_server_msg {
+                            message::ToServer::Draw(draw @ message::Draw::Paint { .. }) => {
+                                let coalesced = pending_paint
+                                    .as_mut()
+                                    .is_some_and(|pending| pending.coalesce(&draw));
+
+                                if !coalesced {
+                                    flush_paint_at = Instant::now() + batch_duration;
+                                    let previous = pending_paint.replace(draw);
+                                    to_send.extend(previous.map(message::ToServer::Draw));
+                                }
+                            }
+                            msg => {
+                                let previous = pending_paint.take();
+                                to_send.extend(previous.map(message::ToServer::Draw));
+                                to_send.push(msg);
+                            }
+                        }
+
+                        for to_server_msg in to_send {
+                            if let message::ToServer::Disconnect = to_server_msg {
+                                let _ = client_to_server.send(to_server_msg).await;
+                                break 'connection ConnectionStatus::NotConnected;
+                            } else if client_to_server.send(to_server_msg).await.is_err() {
+                                break 'connection ConnectionStatus::Dropped;
+                            }
                         }
                     }
 
                     Some(server_msg) = server_to_client.next() => {
                         if let Ok(msg) = server_msg {
                             match msg {
-                                message::ToClient::Disconnect(_) => break ConnectionStatus::Dropped,
+                                message::ToClient::Disconnect(_) => {
+                                    // let the app show the reason
+                                    app_tx.send(Event::Net(NetEvent::Message(Box::new(msg))));
+                                    break ConnectionStatus::Dropped;
+                                }
                                 _ => app_tx.send(Event::Net(NetEvent::Message(Box::new(msg))))
                             }
                         } else {
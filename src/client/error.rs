@@ -3,6 +3,7 @@ use thiserror::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("send error: `{0}`")]
     SendError(String),
@@ -12,6 +13,18 @@ pub enum Error {
     TermError(#[from] crossterm::ErrorKind),
     #[error("IO error")]
     IOError(#[from] std::io::Error),
+    #[error("network message error")]
+    EncodingError(#[from] crate::encoding::Error),
+    #[error("websocket error")]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+    #[error("handshake timed out")]
+    HandshakeTimeout(#[from] tokio::time::Elapsed),
+    #[error("not a termibbl server")]
+    InvalidServer,
+    #[error("incompatible server: {0}")]
+    IncompatibleServer(String),
+    #[error("refused by server: {0}")]
+    Refused(String),
 }
 
 impl<T> From<tokio::sync::mpsc::error::SendError<T>> for Error {
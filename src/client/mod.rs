@@ -1,12 +1,24 @@
 mod app;
 mod app_server;
 mod error;
+mod replay;
 mod ui;
 
 pub use app::App;
+pub use app_server::ServerAddr;
 pub use crossterm::event::Event as InputEvent;
+pub use replay::ReplayPlayer;
 
 use argh::FromArgs;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    export::{ExportFormat, Gallery},
+    message::ProfileKey,
+};
 
 /// play Skribbl.io-like games in the Termibbl
 #[derive(FromArgs, Default)]
@@ -17,10 +29,74 @@ pub struct CliOpts {
     pub username: Option<String>,
 
     #[argh(option, short = 'h')]
-    /// address of server to connect to.
+    /// address of server to connect to, a ws://host:port/path url connects over a websocket.
     pub host: Option<String>,
 
     #[argh(option, short = 'p')]
     /// port of the local server to connect
     pub port: Option<usize>,
+
+    #[argh(option)]
+    /// directory drawings are saved to with ctrl+s, the current one by default
+    pub export_dir: Option<PathBuf>,
+
+    #[argh(option, default = "ExportFormat::Png")]
+    /// format drawings are saved as: png, svg or ansi
+    pub export_format: ExportFormat,
+
+    #[argh(option)]
+    /// file holding the key of your profile, created if it doesn't exist yet
+    pub profile: Option<PathBuf>,
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    s.parse()
+        .ok()
+        .filter(|speed: &f64| speed.is_finite() && *speed > 0.0)
+        .ok_or_else(|| "expected a speed above 0".to_owned())
+}
+
+/// watch a game recorded by a server started with --replays
+#[derive(FromArgs)]
+#[argh(subcommand, name = "replay")]
+pub struct ReplayOpts {
+    #[argh(positional)]
+    /// replay file to play
+    pub file: PathBuf,
+
+    #[argh(option, default = "1.0", from_str_fn(parse_speed))]
+    /// playback speed, 2 plays twice as fast
+    pub speed: f64,
+
+    #[argh(option)]
+    /// round to start watching from
+    pub round: Option<usize>,
+
+    #[argh(option)]
+    /// turn to start watching from, counted from the start of the game
+    pub turn: Option<usize>,
+}
+
+impl CliOpts {
+    /// where drawings are saved when asked to
+    pub fn export_gallery(&self) -> Gallery {
+        Gallery {
+            dir: self.export_dir.clone().unwrap_or_default(),
+            format: self.export_format,
+        }
+    }
+}
+
+/// read the profile key kept in `path`, a new profile is created if there is no such file
+pub fn load_profile(path: &Path) -> io::Result<ProfileKey> {
+    match fs::read_to_string(path) {
+        Ok(key) => ProfileKey::from_str_radix(key.trim(), 16)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key: ProfileKey = rand::random();
+            fs::write(path, format!("{:032x}\n", key))?;
+            Ok(key)
+        }
+        Err(e) => Err(e),
+    }
 }
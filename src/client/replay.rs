@@ -0,0 +1,273 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, MouseEvent};
+use tui::{
+    style::{Color as TuiColor, Style},
+    text::{Span, Spans},
+    widgets::Paragraph,
+    Frame,
+};
+
+use crate::{
+    data::{UserId, Username},
+    message::InitialRoomState,
+    replay::{Replay, ReplayEntry},
+};
+
+use super::{
+    ui::{Action, Backend, Room, View},
+    App,
+};
+
+/// slowest and fastest playback speed
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 16.0;
+
+/// plays a recorded game back in a room view, as a spectator
+pub struct ReplayPlayer {
+    replay: Replay,
+    room: Room,
+    /// index of the next entry to play
+    next: usize,
+    /// position in the replay, in milliseconds, when playback was last (re)started
+    base_millis: u64,
+    /// when playback was last (re)started, none while paused
+    resumed_at: Option<Instant>,
+    speed: f64,
+    /// only the latest scheduled tick plays entries, older ones are ignored
+    tick: u64,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay, speed: f64) -> Self {
+        let room = Self::empty_room(&replay);
+
+        Self {
+            replay,
+            room,
+            next: 0,
+            base_millis: 0,
+            resumed_at: Some(Instant::now()),
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+            tick: 0,
+        }
+    }
+
+    /// the room as it was when the game started
+    fn empty_room(replay: &Replay) -> Room {
+        Room::new(InitialRoomState {
+            username: Username::new("replay".to_owned(), UserId::MAX),
+            room: replay.header.room.clone(),
+            game: None,
+            is_spectator: true,
+            resume_token: None,
+        })
+    }
+
+    /// position in the replay in milliseconds
+    fn position(&self) -> u64 {
+        let played = self.resumed_at.map_or(0, |at| {
+            (at.elapsed().as_millis() as f64 * self.speed) as u64
+        });
+        self.base_millis + played
+    }
+
+    fn is_finished(&self) -> bool { self.next >= self.replay.entries.len() }
+
+    /// play every entry that is due, returns when to tick again unless paused or finished
+    pub fn on_tick(&mut self, tick: u64) -> Option<Duration> {
+        if tick != self.tick {
+            return None;
+        }
+
+        let position = self.position();
+        while let Some(entry) = self.replay.entries.get(self.next) {
+            if entry.millis > position {
+                break;
+            }
+            self.room.process_event(entry.event.clone());
+            self.next += 1;
+        }
+
+        self.until_next_entry()
+    }
+
+    /// time until the next entry is due at the current speed, at most a second so the clock
+    /// keeps moving
+    pub fn until_next_entry(&self) -> Option<Duration> {
+        self.resumed_at?;
+        let entry = self.replay.entries.get(self.next)?;
+        let millis = entry.millis.saturating_sub(self.position()) as f64 / self.speed;
+
+        Some(Duration::from_millis(millis as u64).min(Duration::from_secs(1)))
+    }
+
+    /// invalidate scheduled ticks, returns the id of the next one
+    pub fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// restart the clock from the current position, after pausing or changing the speed
+    fn rebase(&mut self) {
+        self.base_millis = self.position();
+        if self.resumed_at.is_some() {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        self.base_millis = self.position();
+        self.resumed_at = match self.resumed_at {
+            Some(_) => None,
+            None => Some(Instant::now()),
+        };
+    }
+
+    fn change_speed(&mut self, factor: f64) {
+        self.rebase();
+        self.speed = (self.speed * factor).clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// jump to just before the entry at `index`, replaying everything before it at once
+    fn seek(&mut self, index: usize) {
+        let index = index.min(self.replay.entries.len());
+        if index < self.next {
+            self.room = Self::empty_room(&self.replay);
+            self.next = 0;
+        }
+
+        for entry in &self.replay.entries[self.next..index] {
+            self.room.process_event(entry.event.clone());
+        }
+        self.next = index;
+
+        self.base_millis = match self.replay.entries.get(index) {
+            Some(entry) => entry.millis,
+            None => self.replay.duration(),
+        };
+        if self.resumed_at.is_some() {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    /// indices of the entries that start a round or a turn
+    fn marks(&self, is_mark: impl Fn(&ReplayEntry) -> bool) -> Vec<usize> {
+        self.replay
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| is_mark(entry))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn turn_starts(&self) -> Vec<usize> { self.marks(|entry| entry.is_turn_start()) }
+
+    fn round_starts(&self) -> Vec<usize> { self.marks(|entry| entry.round_start().is_some()) }
+
+    /// seek to the next mark, or back to the start of the current one, or the one before that
+    /// if it just started
+    fn seek_mark(&mut self, marks: Vec<usize>, forward: bool) {
+        let target = if forward {
+            marks.into_iter().find(|idx| *idx >= self.next)
+        } else {
+            let current = self.next.saturating_sub(1);
+            marks.into_iter().rev().find(|idx| *idx < current)
+        };
+
+        match target {
+            Some(index) => self.seek(index),
+            None if forward => self.seek(self.replay.entries.len()),
+            None => self.seek(0),
+        }
+    }
+
+    /// start playing at the start of a round, counted from 1
+    pub fn seek_round(&mut self, round: usize) {
+        let target = self
+            .replay
+            .entries
+            .iter()
+            .position(|entry| entry.round_start() == Some(round));
+
+        if let Some(index) = target {
+            self.seek(index);
+        }
+    }
+
+    /// start playing at the start of a turn, counted from 1 at the start of the game
+    pub fn seek_turn(&mut self, turn: usize) {
+        if let Some(index) = self.turn_starts().get(turn.saturating_sub(1)) {
+            self.seek(*index);
+        }
+    }
+
+    fn status(&self) -> Spans<'_> {
+        let turn = self
+            .turn_starts()
+            .iter()
+            .filter(|idx| **idx < self.next)
+            .count();
+
+        let state = if self.is_finished() {
+            "■"
+        } else if self.resumed_at.is_none() {
+            "❚❚"
+        } else {
+            "▶"
+        };
+
+        Spans::from(vec![
+            Span::styled(
+                format!(
+                    " {} {}x {} / {} turn {}",
+                    state,
+                    self.speed,
+                    clock(self.position().min(self.replay.duration())),
+                    clock(self.replay.duration()),
+                    turn,
+                ),
+                Style::default().fg(TuiColor::Black).bg(TuiColor::Gray),
+            ),
+            Span::raw(" space pause, +/- speed, ←/→ turn, pgup/pgdn round"),
+        ])
+    }
+}
+
+/// minutes and seconds
+fn clock(millis: u64) -> String {
+    let secs = millis / 1000;
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+/// after a key changed the playback, play on from the new position
+fn reschedule() -> Action { Box::new(|app: &mut App| app.schedule_replay_tick()) }
+
+impl View for ReplayPlayer {
+    fn draw(&self, frame: &mut Frame<Backend>, app: &App) {
+        self.room.draw(frame, app);
+
+        let footer = self.room.footer(frame.size());
+        frame.render_widget(Paragraph::new(self.status()), footer);
+    }
+
+    fn on_key_event(&mut self, event: KeyEvent) -> Action {
+        match event.code {
+            KeyCode::Char(' ') => self.toggle_pause(),
+            KeyCode::Char('+') => self.change_speed(2.0),
+            KeyCode::Char('-') => self.change_speed(0.5),
+            KeyCode::Right => self.seek_mark(self.turn_starts(), true),
+            KeyCode::Left => self.seek_mark(self.turn_starts(), false),
+            KeyCode::PageDown => self.seek_mark(self.round_starts(), true),
+            KeyCode::PageUp => self.seek_mark(self.round_starts(), false),
+            _ => return self.room.on_key_event(event),
+        }
+
+        reschedule()
+    }
+
+    fn on_mouse_event(&mut self, event: MouseEvent) -> Action { self.room.on_mouse_event(event) }
+
+    fn on_resize(&mut self, size: (u16, u16)) { self.room.on_resize(size) }
+}
@@ -1,269 +1,1973 @@
+use std::{cmp::Reverse, collections::HashMap, env, io::Stdout};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use tui::{
+    backend::CrosstermBackend,
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color as TuiColor, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Widget, Wrap},
+    Frame,
+};
+
 use crate::{
-    client::app::{App, AppCanvas},
-    client::error::Result,
-    data::{Coord, Message},
-    server::skribbl::{PlayerState, SkribblState},
+    data::{
+        Color, ColorDepth, Coord, GameInfo, GameMode, GameState, PlayerData, Scoring, TurnPhase,
+        Username, WordHint,
+    },
+    export::Drawing,
+    message::{
+        self, Brush, BrushTip, ChatMessage, Draw, GameEvent, InitialRoomState, LeaderCommand,
+        Leaderboard, LeaderboardPeriod, RoomEvent, RoomInfo, RoomRequest, Shape, ShapeKind,
+        ToServer,
+    },
+    server::{CHOOSE_WORDS_TIME, FINISH_SECS, REVEAL_PHASE_SECS, ROUND_START_SECS},
+    utils,
 };
 
-use super::Username;
-use tui::{
-    backend::Backend,
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
-    widgets::{Block, Borders, List, Paragraph, Text, Widget},
-    Terminal,
-};
+use super::{app::App, app_server::ServerAddr};
+
+pub type Backend = CrosstermBackend<Stdout>;
+
+/// what to do with the app after a view handled an input event
+pub type Action = Box<dyn FnOnce(&mut App)>;
+
+/// width of the player list and chat next to the canvas
+const SIDEBAR_WIDTH: u16 = 32;
+
+/// colors the drawing player can pick from
+const PALETTE: [Color; 16] = [
+    Color::Black,
+    Color::DarkGray,
+    Color::Gray,
+    Color::White,
+    Color::Red,
+    Color::LightRed,
+    Color::Yellow,
+    Color::LightYellow,
+    Color::Green,
+    Color::LightGreen,
+    Color::Cyan,
+    Color::LightCyan,
+    Color::Blue,
+    Color::LightBlue,
+    Color::Magenta,
+    Color::LightMagenta,
+];
+
+/// draw times the room leader can cycle through
+const DRAW_TIMES: [usize; 5] = [30, 60, 90, 120, 180];
+
+pub fn backend() -> Backend { CrosstermBackend::new(std::io::stdout()) }
+
+fn no_action() -> Action { Box::new(|_| {}) }
+
+fn send(msg: ToServer) -> Action { Box::new(move |app| app.server().send_message(msg)) }
+
+fn notify(notification: &str) -> Action {
+    let notification = notification.to_owned();
+    Box::new(move |app| app.display_notif(notification))
+}
+
+pub trait View {
+    fn draw(&self, frame: &mut Frame<Backend>, app: &App);
+
+    fn on_key_event(&mut self, _event: KeyEvent) -> Action { no_action() }
+
+    fn on_mouse_event(&mut self, _event: MouseEvent) -> Action { no_action() }
+
+    fn on_resize(&mut self, _size: (u16, u16)) {}
+}
+
+/// single line text input
+#[derive(Default)]
+pub struct Input {
+    content: String,
+    focus: bool,
+}
+
+impl Input {
+    pub fn content(&self) -> &str { &self.content }
+
+    pub fn set_content(&mut self, content: String) { self.content = content; }
+
+    pub fn take(&mut self) -> String { std::mem::take(&mut self.content) }
+
+    pub fn has_focus(&self) -> bool { self.focus }
+
+    pub fn set_focus(&mut self, focus: bool) { self.focus = focus; }
+
+    /// edit the content with a key press, returns whether the key was used
+    pub fn on_key(&mut self, event: KeyEvent) -> bool {
+        match event.code {
+            KeyCode::Char(c) if !event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.content.push(c);
+                true
+            }
+            KeyCode::Backspace => self.content.pop().is_some(),
+            _ => false,
+        }
+    }
+
+    /// draw in a bordered box, the cursor is placed at the end when focused
+    fn draw(&self, frame: &mut Frame<Backend>, area: Rect, title: &str) {
+        let border_style = if self.focus {
+            Style::default().fg(TuiColor::Yellow)
+        } else {
+            Style::default()
+        };
+
+        // only show the end of the content if it doesn't fit
+        let width = area.width.saturating_sub(3) as usize;
+        let skip = self.content.chars().count().saturating_sub(width);
+        let visible: String = self.content.chars().skip(skip).collect();
+        let cursor_x = area.x + 1 + visible.chars().count() as u16;
+
+        let input = Paragraph::new(visible).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style)
+                .title(title),
+        );
+        frame.render_widget(input, area);
+
+        if self.focus {
+            frame.set_cursor(cursor_x, area.y + 1);
+        }
+    }
+}
+
+/// first screen, connects to a server and asks for a room
+pub struct StartMenu {
+    pub host_input: Input,
+    pub username_input: Input,
+    room_key_input: Input,
+}
+
+impl Default for StartMenu {
+    fn default() -> Self {
+        let mut host_input = Input::default();
+        host_input.set_focus(true);
+
+        Self {
+            host_input,
+            username_input: Input::default(),
+            room_key_input: Input::default(),
+        }
+    }
+}
+
+impl StartMenu {
+    pub fn new(addr: Option<String>, username: Option<String>) -> Self {
+        let mut menu = Self::default();
+        menu.host_input.set_content(addr.unwrap_or_default());
+        menu.username_input
+            .set_content(username.unwrap_or_default());
+
+        menu
+    }
+
+    pub fn on_connection_status_changed(&mut self, is_connected: bool) {
+        self.focus(if is_connected { 1 } else { 0 });
+    }
+
+    fn inputs_mut(&mut self) -> [&mut Input; 3] {
+        [
+            &mut self.host_input,
+            &mut self.username_input,
+            &mut self.room_key_input,
+        ]
+    }
+
+    fn focused(&self) -> usize {
+        [&self.host_input, &self.username_input, &self.room_key_input]
+            .iter()
+            .position(|input| input.has_focus())
+            .unwrap_or_default()
+    }
+
+    fn focus(&mut self, idx: usize) {
+        for (i, input) in self.inputs_mut().iter_mut().enumerate() {
+            input.set_focus(i == idx);
+        }
+    }
+
+    fn connect(&self) -> Action {
+        match ServerAddr::resolve(self.host_input.content()) {
+            Some(addr) => Box::new(move |app| {
+                let server = app.server();
+                // already connected to this server
+                if !server.is_connected() || server.addr() != Some(addr.to_string()) {
+                    app.connect_to_server(addr)
+                }
+            }),
+            None => notify("Could not resolve the server address"),
+        }
+    }
+
+    fn request_room(&mut self, req: RoomRequest) -> Action {
+        let name = self.username_input.content().trim().to_owned();
+
+        if name.is_empty() {
+            self.focus(1);
+            return notify("Pick a username first");
+        }
+
+        send(ToServer::RequestRoom(Some(name), req))
+    }
+}
+
+impl View for StartMenu {
+    fn draw(&self, frame: &mut Frame<Backend>, app: &App) {
+        use Constraint::*;
+
+        let area = centered_rect(50, 18, frame.size());
+        let block = Block::default().borders(Borders::ALL).title("Termibbl");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Length(3), Length(3), Length(3), Length(2), Min(0)].as_ref())
+            .split(inner);
+
+        self.host_input.draw(frame, chunks[0], "Server address");
+        self.username_input.draw(frame, chunks[1], "Username");
+        self.room_key_input
+            .draw(frame, chunks[2], "Room key (empty to find a game)");
+
+        let status = app.server().connection_status();
+        let status_style = if app.server().is_connected() {
+            Style::default().fg(TuiColor::Green)
+        } else {
+            Style::default().fg(TuiColor::Red)
+        };
+        frame.render_widget(
+            Paragraph::new(Span::styled(format!(" {}", status), status_style)),
+            chunks[3],
+        );
+
+        let help = vec![
+            Spans::from(" [Enter] connect / join the room"),
+            Spans::from(" [F2] create a room  [F3] spectate"),
+            Spans::from(" [F4] leaderboard"),
+            Spans::from(" [Tab] next field    [Esc] back / quit"),
+        ];
+        frame.render_widget(
+            Paragraph::new(help).style(Style::default().fg(TuiColor::DarkGray)),
+            chunks[4],
+        );
+
+        draw_notifications(frame, app);
+    }
+
+    fn on_key_event(&mut self, event: KeyEvent) -> Action {
+        let focused = self.focused();
+
+        match event.code {
+            KeyCode::Tab => self.focus((focused + 1) % 3),
+            KeyCode::BackTab => self.focus((focused + 2) % 3),
+            KeyCode::Enter if focused == 0 => return self.connect(),
+            KeyCode::Enter => {
+                let key = self.room_key_input.content().trim().to_owned();
+                let req = if key.is_empty() {
+                    RoomRequest::Find
+                } else {
+                    RoomRequest::Join(key)
+                };
+
+                return self.request_room(req);
+            }
+            KeyCode::F(2) => return self.request_room(RoomRequest::Create),
+            KeyCode::F(3) => {
+                let key = self.room_key_input.content().trim().to_owned();
+                if key.is_empty() {
+                    self.focus(2);
+                    return notify("Enter the key of the room to spectate");
+                }
+
+                return self.request_room(RoomRequest::Spectate(key));
+            }
+            KeyCode::F(4) => return Box::new(|app| app.show_leaderboard()),
+            _ => {
+                self.inputs_mut()[focused].on_key(event);
+            }
+        };
+
+        no_action()
+    }
+}
+
+/// leaderboard as last received from the server
+enum Board {
+    Loading,
+    Unavailable, // server doesn't keep profiles
+    Loaded(Leaderboard),
+}
+
+/// best players of the server, opened from the start menu
+pub struct LeaderboardView {
+    /// start menu to go back to
+    menu: StartMenu,
+    period: LeaderboardPeriod,
+    board: Board,
+}
+
+impl LeaderboardView {
+    pub fn new(menu: StartMenu) -> Self {
+        Self {
+            menu,
+            period: LeaderboardPeriod::AllTime,
+            board: Board::Loading,
+        }
+    }
+
+    /// ask the server for the leaderboard of the current period
+    pub fn request(&self) -> ToServer { ToServer::Leaderboard(self.period) }
+
+    pub fn into_menu(self) -> StartMenu { self.menu }
+
+    pub fn set_leaderboard(&mut self, leaderboard: Option<Leaderboard>) {
+        self.board = match leaderboard {
+            // only show the answer to the latest request
+            Some(board) if board.period != self.period => return,
+            Some(board) => Board::Loaded(board),
+            None => Board::Unavailable,
+        };
+    }
+
+    fn lines(&self) -> Vec<Spans<'_>> {
+        let board = match &self.board {
+            Board::Loading => return vec![Spans::from(" Loading..")],
+            Board::Unavailable => return vec![Spans::from(" This server doesn't keep profiles")],
+            Board::Loaded(board) if board.entries.is_empty() => {
+                return vec![Spans::from(" No games played yet")]
+            }
+            Board::Loaded(board) => board,
+        };
+
+        let header = Spans::from(Span::styled(
+            format!(
+                " {:>2}  {:<16} {:>7} {:>5} {:>6} {:>6}",
+                "#", "Name", "Score", "Wins", "Games", "Words"
+            ),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+
+        std::iter::once(header)
+            .chain(
+                board
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (name, stats))| {
+                        Spans::from(format!(
+                            " {:>2}. {:<16} {:>7} {:>5} {:>6} {:>6}",
+                            idx + 1,
+                            name.chars().take(16).collect::<String>(),
+                            stats.total_score,
+                            stats.wins,
+                            stats.games_played,
+                            stats.words_guessed
+                        ))
+                    }),
+            )
+            .collect()
+    }
+}
+
+impl View for LeaderboardView {
+    fn draw(&self, frame: &mut Frame<Backend>, app: &App) {
+        let title = match self.period {
+            LeaderboardPeriod::AllTime => "Leaderboard: all time",
+            LeaderboardPeriod::Weekly => "Leaderboard: this week",
+        };
+
+        let mut lines = self.lines();
+        lines.push(Spans::from(""));
+        lines.push(Spans::from(Span::styled(
+            " [Tab] all time / this week  [Esc] back",
+            Style::default().fg(TuiColor::DarkGray),
+        )));
+
+        draw_popup(frame, frame.size(), title, lines);
+        draw_notifications(frame, app);
+    }
+
+    fn on_key_event(&mut self, event: KeyEvent) -> Action {
+        if let KeyCode::Tab | KeyCode::BackTab = event.code {
+            self.period = match self.period {
+                LeaderboardPeriod::AllTime => LeaderboardPeriod::Weekly,
+                LeaderboardPeriod::Weekly => LeaderboardPeriod::AllTime,
+            };
+            self.board = Board::Loading;
+
+            return send(self.request());
+        }
+
+        no_action()
+    }
+}
+
+/// areas of the room screen
+struct RoomLayout {
+    header: Rect,
+    canvas: Rect,
+    canvas_view: Option<CanvasView>, // how the canvas is mapped onto the terminal, if in a game
+    palette: Rect,
+    players: Rect,
+    chat: Rect,
+    input: Rect,
+}
+
+/// a joined room, shows the lobby or the ongoing game
+pub struct Room {
+    pub username: Username,
+    info: RoomInfo,
+    game: Option<GameInfo>,
+    is_spectator: bool,
+    messages: Vec<ChatMessage>,
+    chat_input: Input,
+    color: Color,
+    color_depth: ColorDepth,
+    color_picker: Option<Input>, // custom color input while the color picker is open
+    brush: Brush,
+    tool: Tool,
+    canvas_mode: CanvasMode,
+    last_cell: Option<Coord>, // last painted cell while dragging the mouse, relative to the canvas
+    shape_preview: Option<Shape>, // shape being dragged out, sent on releasing the mouse
+    selected_choice: usize,   // word highlighted in the word picker
+    selected_user: usize,     // user highlighted in the lobby, for leader commands
+    size: Rect,
+}
+
+impl Room {
+    pub fn new(initial_state: InitialRoomState) -> Self {
+        let (width, height) = crossterm::terminal::size().unwrap_or((80, 24));
+        let mut chat_input = Input::default();
+        chat_input.set_focus(true);
+
+        // room info is taken before we are added to the room
+        let mut info = initial_state.room;
+        if !initial_state.is_spectator && !info.connected_users.contains(&initial_state.username) {
+            info.connected_users.push(initial_state.username.clone());
+        }
+
+        Self {
+            username: initial_state.username,
+            info,
+            game: initial_state.game,
+            is_spectator: initial_state.is_spectator,
+            messages: Vec::new(),
+            chat_input,
+            color: Color::Black,
+            color_depth: detect_color_depth(),
+            color_picker: None,
+            brush: Brush::default(),
+            tool: Tool::Brush,
+            canvas_mode: CanvasMode::HalfBlock,
+            last_cell: None,
+            shape_preview: None,
+            selected_choice: 0,
+            selected_user: 0,
+            size: Rect::new(0, 0, width, height),
+        }
+    }
+
+    pub fn get_current_view(&self) -> &dyn View { self }
+
+    /// the line below the canvas, only used by the palette of the drawing player
+    pub fn footer(&self, area: Rect) -> Rect { self.layout(area).palette }
+
+    pub fn get_current_view_mut(&mut self) -> &mut dyn View { self }
+
+    pub fn process_event(&mut self, event: RoomEvent) {
+        match event {
+            RoomEvent::Chat(msg) => self.messages.push(msg),
+            RoomEvent::GameEvent(event) => self.process_game_event(event),
+            RoomEvent::StartGame(game) => {
+                self.selected_choice = 0;
+                self.game = Some(game);
+            }
+            RoomEvent::EndGame => self.game = None,
+            RoomEvent::UserJoin(username) => {
+                if !self.info.connected_users.contains(&username) {
+                    self.info.connected_users.push(username);
+                }
+            }
+            RoomEvent::UserLeave(username) => {
+                self.info.connected_users.retain(|user| user != &username);
+                self.info.spectators.retain(|user| user != &username);
+            }
+            RoomEvent::RoomUpdate(info) => self.info = info,
+        }
+    }
+
+    fn process_game_event(&mut self, event: GameEvent) {
+        let draw_time = self.info.game_opts.draw_time as u64;
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return,
+        };
+
+        match event {
+            GameEvent::PlayerJoin(player) => game.players.push(player),
+            GameEvent::PlayerGuessed(username) => {
+                let secs = draw_time
+                    .saturating_sub(game.remaining_secs_in_phase())
+                    .max(1);
+
+                if let Some(player) = game.get_player_mut(username.id()) {
+                    player.secs_to_solve_turn = secs;
+                }
+            }
+            GameEvent::PlayerListUpdate(players) => game.players = players,
+            GameEvent::StateUpdate(state) => {
+                // state updates come with the start of each phase
+                game.next_phase_timestamp = utils::get_time_now() + phase_secs(&state, draw_time);
+
+                if let GameState::RoundStart(round_num) = state {
+                    game.round_num = round_num;
+                }
+
+                game.state = state;
+                self.selected_choice = 0;
+                self.last_cell = None;
+                self.shape_preview = None;
+                self.color_picker = None;
+            }
+            GameEvent::WordHint((idx, c)) => {
+                if let Some(WordHint::Hint { hints, .. }) = game.state.as_turn_drawing_mut() {
+                    hints.insert(idx, c);
+                }
+            }
+            GameEvent::Draw(draw) => draw.apply(&mut game.canvas, game.dimensions),
+            GameEvent::CanvasPatch(patch) => patch.apply(&mut game.canvas),
+        }
+    }
+
+    fn is_leader(&self) -> bool { self.info.leader.as_ref() == Some(&self.username) }
+
+    /// the finished drawing of this turn, once its word is revealed
+    fn revealed_drawing(&self) -> Option<Drawing> {
+        let game = self.game.as_ref()?;
+
+        match &game.state.as_turn()?.phase {
+            TurnPhase::RevealWord { word, .. } => Some(Drawing {
+                key: self.info.key.clone(),
+                round: game.round_num,
+                word: word.clone(),
+                dimensions: game.dimensions,
+                canvas: game.canvas.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// whether it is our turn to draw
+    fn is_drawing(&self) -> bool {
+        self.game
+            .as_ref()
+            .and_then(|game| game.state.as_turn())
+            .is_some_and(|turn| {
+                turn.who_is_drawing == self.username.id()
+                    && matches!(turn.phase, TurnPhase::Drawing(_))
+            })
+    }
+
+    /// words to pick from, only when it's our turn to choose
+    fn word_choices(&self) -> Option<&[String]> {
+        let turn = self.game.as_ref()?.state.as_turn()?;
+
+        match &turn.phase {
+            TurnPhase::ChoosingWord(choices)
+                if turn.who_is_drawing == self.username.id() && !choices.is_empty() =>
+            {
+                Some(choices)
+            }
+            _ => None,
+        }
+    }
+
+    fn layout(&self, area: Rect) -> RoomLayout {
+        use Constraint::*;
+
+        let main_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Min(0), Length(SIDEBAR_WIDTH)].as_ref())
+            .split(area);
+
+        let canvas_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Length(1), Min(0), Length(1)].as_ref())
+            .split(main_chunks[0]);
+
+        let players_height = (self.player_lines().len() as u16 + 2).min(area.height / 2);
+        let sidebar_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Length(players_height), Min(0), Length(3)].as_ref())
+            .split(main_chunks[1]);
+
+        // scale the canvas to fit, with room for its border
+        let border = Block::default().borders(Borders::ALL);
+        let canvas_view = self.game.as_ref().map(|game| {
+            CanvasView::fit(
+                border.inner(canvas_chunks[1]),
+                game.dimensions,
+                self.canvas_mode,
+            )
+        });
+        let canvas = canvas_view.map_or(canvas_chunks[1], |view| Rect {
+            width: view.area.width + 2,
+            height: view.area.height + 2,
+            ..canvas_chunks[1]
+        });
+
+        RoomLayout {
+            header: canvas_chunks[0],
+            canvas,
+            canvas_view,
+            palette: canvas_chunks[2],
+            players: sidebar_chunks[0],
+            chat: sidebar_chunks[1],
+            input: sidebar_chunks[2],
+        }
+    }
+
+    fn header(&self) -> Spans<'_> {
+        let game = match &self.game {
+            Some(game) => game,
+            None if self.is_spectator => return Spans::from(" Spectating the lobby"),
+            None => return Spans::from(" Lobby"),
+        };
+
+        let mut spans = vec![Span::raw(format!(
+            " Round {}/{} [time: {}] ",
+            game.round_num,
+            game.num_of_rounds,
+            game.remaining_secs_in_phase()
+        ))];
+
+        if let Some(turn) = game.state.as_turn() {
+            let drawing = game
+                .who_is_drawing()
+                .map_or_else(|| "?".to_owned(), Username::to_string);
+
+            match &turn.phase {
+                TurnPhase::ChoosingWord(_) => {
+                    spans.push(Span::raw(format!("{} is choosing a word", drawing)))
+                }
+                TurnPhase::Drawing(WordHint::Draw(word)) => spans.push(Span::styled(
+                    format!("Draw: {}", word),
+                    Style::default().fg(TuiColor::Black).bg(TuiColor::Red),
+                )),
+                TurnPhase::Drawing(hint) => {
+                    spans.push(Span::raw(format!("{} is drawing ", drawing)));
+                    spans.push(Span::styled(
+                        hint_text(hint),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ));
+                }
+                TurnPhase::RevealWord { .. } => {}
+            }
+        }
+
+        if self.is_spectator {
+            spans.push(Span::styled(
+                " [spectating]",
+                Style::default().fg(TuiColor::DarkGray),
+            ));
+        }
+
+        Spans::from(spans)
+    }
+
+    /// lines of the player list, grouped by team in a team game
+    fn player_lines(&self) -> Vec<ListItem<'_>> {
+        let game = match &self.game {
+            Some(game) => game,
+            None => return self.lobby_user_lines(),
+        };
+
+        let who_is_drawing = game.state.as_turn().map(|turn| turn.who_is_drawing);
+        let mut players: Vec<_> = game.players.iter().collect();
+        players.sort_by_key(|pl| Reverse(pl.score));
+
+        let player_line = |indent: &str, player: &PlayerData| {
+            let style = if Some(player.name.id()) == who_is_drawing {
+                Style::default().bg(TuiColor::Cyan)
+            } else if player.solved_current_round() {
+                Style::default().fg(TuiColor::Green)
+            } else if player.name == self.username {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+
+            ListItem::new(Span::styled(
+                format!("{}{}: {}", indent, player.name, player.score),
+                style,
+            ))
+        };
+
+        let mut lines = Vec::new();
+        if game.is_team_game() {
+            for (team, score) in game.team_scores() {
+                lines.push(ListItem::new(Span::styled(
+                    format!("Team {}: {}", team + 1, score),
+                    Style::default().add_modifier(Modifier::UNDERLINED),
+                )));
+
+                for player in players.iter().filter(|pl| pl.team == Some(team)) {
+                    lines.push(player_line("  ", player));
+                }
+            }
+        } else {
+            lines.extend(players.into_iter().map(|player| player_line("", player)));
+        }
+
+        lines.extend(self.spectator_lines());
+        lines
+    }
+
+    fn lobby_user_lines(&self) -> Vec<ListItem<'_>> {
+        let mut lines: Vec<ListItem> = self
+            .info
+            .connected_users
+            .iter()
+            .enumerate()
+            .map(|(idx, user)| {
+                let is_leader = self.info.leader.as_ref() == Some(user);
+                let mut style = Style::default();
+
+                if self.is_leader() && idx == self.selected_user {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                if user == &self.username {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+
+                let suffix = if is_leader { " (leader)" } else { "" };
+                ListItem::new(Span::styled(format!("{}{}", user, suffix), style))
+            })
+            .collect();
+
+        lines.extend(self.spectator_lines());
+        lines
+    }
+
+    fn spectator_lines(&self) -> Vec<ListItem<'_>> {
+        self.info
+            .spectators
+            .iter()
+            .map(|user| {
+                ListItem::new(Span::styled(
+                    format!("{} (spectator)", user),
+                    Style::default().fg(TuiColor::DarkGray),
+                ))
+            })
+            .collect()
+    }
+
+    fn lobby_lines(&self) -> Vec<Spans<'_>> {
+        let opts = &self.info.game_opts;
+        let mode = match opts.game_mode {
+            GameMode::FreeForAll => "free for all".to_owned(),
+            GameMode::Teams(size) => format!("teams of {}", size),
+        };
+        let packs = if opts.word_packs.is_empty() {
+            "all".to_owned()
+        } else {
+            opts.word_packs.join(", ")
+        };
+
+        let mut lines = vec![
+            Spans::from(format!("Room key:   {}", self.info.key)),
+            Spans::from(format!("Rounds:     {}", opts.number_of_rounds)),
+            Spans::from(format!("Draw time:  {}s", opts.draw_time)),
+            Spans::from(format!("Mode:       {}", mode)),
+            Spans::from(format!("Scoring:    {:?}", opts.scoring)),
+            Spans::from(format!("Word packs: {}", packs)),
+            Spans::from(""),
+        ];
+
+        let help = Style::default().fg(TuiColor::DarkGray);
+        match &self.info.leader {
+            None => lines.push(Spans::from(
+                "The game starts once enough players have joined.",
+            )),
+            Some(_) if self.is_leader() => {
+                lines.push(Spans::from(Span::styled("[F5] start the game", help)));
+                lines.push(Spans::from(Span::styled(
+                    "[F2] rounds  [F3] draw time  [F4] mode  [F8] scoring",
+                    help,
+                )));
+                lines.push(Spans::from(Span::styled(
+                    "[Up/Down] select player  [F6] kick  [F7] make leader",
+                    help,
+                )));
+            }
+            Some(leader) => lines.push(Spans::from(format!(
+                "Waiting for {} to start the game.",
+                leader
+            ))),
+        }
+
+        lines
+    }
+
+    /// popup shown over the canvas between turns
+    fn draw_phase_overlay(&self, frame: &mut Frame<Backend>, area: Rect) {
+        let game = match &self.game {
+            Some(game) => game,
+            None => return,
+        };
+
+        match &game.state {
+            GameState::RoundStart(round_num) => draw_popup(
+                frame,
+                area,
+                "",
+                vec![Spans::from(format!(
+                    "Round {} of {}",
+                    round_num, game.num_of_rounds
+                ))],
+            ),
+
+            GameState::Playing(turn) => {
+                if let TurnPhase::RevealWord {
+                    word,
+                    scores,
+                    timed_out,
+                } = &turn.phase
+                {
+                    let mut lines = Vec::new();
+                    if *timed_out {
+                        lines.push(Spans::from("Time is up!"));
+                    }
+                    lines.extend(
+                        scores
+                            .iter()
+                            .map(|(name, score)| Spans::from(format!("{}: +{}", name, score))),
+                    );
+                    lines.push(Spans::from(""));
+                    lines.push(Spans::from("Ctrl+S to save the drawing"));
+
+                    draw_popup(frame, area, &format!("The word was: {}", word), lines);
+                }
+            }
+
+            GameState::Finish => {
+                let lines = if game.is_team_game() {
+                    let mut teams = game.team_scores();
+                    teams.sort_by(|(_, a), (_, b)| b.cmp(a));
+                    teams
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, (team, score))| {
+                            Spans::from(format!("{}. Team {}: {}", idx + 1, team + 1, score))
+                        })
+                        .collect()
+                } else {
+                    let mut players: Vec<_> = game.players.iter().collect();
+                    players.sort_by_key(|pl| Reverse(pl.score));
+                    players
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, pl)| {
+                            Spans::from(format!("{}. {}: {}", idx + 1, pl.name, pl.score))
+                        })
+                        .collect()
+                };
+
+                draw_popup(frame, area, "Game over", lines);
+            }
+        }
+    }
+
+    fn on_leader_key(&mut self, code: KeyCode) -> Option<Action> {
+        let mut opts = self.info.game_opts.clone();
+        let selected_user = self.info.connected_users.get(self.selected_user);
+
+        let cmd = match code {
+            KeyCode::F(5) => LeaderCommand::StartGame,
+            KeyCode::F(6) => LeaderCommand::Kick(selected_user?.id()),
+            KeyCode::F(7) => LeaderCommand::SetLeader(selected_user?.id()),
+            KeyCode::F(2) => {
+                opts.number_of_rounds = opts.number_of_rounds % 10 + 1;
+                LeaderCommand::SetGameOpts(opts)
+            }
+            KeyCode::F(3) => {
+                opts.draw_time = DRAW_TIMES
+                    .iter()
+                    .find(|secs| **secs > opts.draw_time)
+                    .copied()
+                    .unwrap_or(DRAW_TIMES[0]);
+                LeaderCommand::SetGameOpts(opts)
+            }
+            KeyCode::F(4) => {
+                opts.game_mode = match opts.game_mode {
+                    GameMode::FreeForAll => GameMode::Teams(2),
+                    GameMode::Teams(2) => GameMode::Teams(3),
+                    GameMode::Teams(_) => GameMode::FreeForAll,
+                };
+                LeaderCommand::SetGameOpts(opts)
+            }
+            KeyCode::F(8) => {
+                opts.scoring = match opts.scoring {
+                    Scoring::Classic => Scoring::Linear,
+                    Scoring::Linear => Scoring::Flat,
+                    Scoring::Flat => Scoring::Classic,
+                };
+                LeaderCommand::SetGameOpts(opts)
+            }
+            KeyCode::Up => {
+                self.selected_user = self.selected_user.saturating_sub(1);
+                return Some(no_action());
+            }
+            KeyCode::Down => {
+                let last = self.info.connected_users.len().saturating_sub(1);
+                self.selected_user = (self.selected_user + 1).min(last);
+                return Some(no_action());
+            }
+            _ => return None,
+        };
+
+        Some(send(ToServer::Leader(cmd)))
+    }
+
+    fn choose_word(&self, idx: usize) -> Action {
+        match self.word_choices().and_then(|choices| choices.get(idx)) {
+            Some(word) => send(ToServer::ChooseWord(word.clone())),
+            None => no_action(),
+        }
+    }
+
+    fn open_color_picker(&mut self) {
+        let mut input = Input::default();
+        input.set_focus(true);
+        self.color_picker = Some(input);
+    }
+
+    fn resize_brush(&mut self, by: i8) {
+        let radius = self.brush.radius as i8 + by;
+        self.brush.radius = radius.clamp(0, Brush::MAX_RADIUS as i8) as u8;
+    }
+
+    fn toggle_brush_tip(&mut self) {
+        self.brush.tip = match self.brush.tip {
+            BrushTip::Round => BrushTip::Square,
+            BrushTip::Square => BrushTip::Round,
+        };
+    }
+
+    fn shape_draw(&self, shape: Shape) -> Draw {
+        Draw::Shape {
+            shape,
+            color: self.color,
+            brush: self.brush,
+        }
+    }
+
+    /// draw locally and let the others in the room know
+    fn draw_on_canvas(&mut self, draw: Draw) -> Action {
+        if let Some(game) = &mut self.game {
+            draw.apply(&mut game.canvas, game.dimensions);
+        }
+
+        send(ToServer::Draw(draw))
+    }
+}
+
+impl View for Room {
+    fn draw(&self, frame: &mut Frame<Backend>, app: &App) {
+        let layout = self.layout(frame.size());
+
+        frame.render_widget(Paragraph::new(self.header()), layout.header);
+
+        match &self.game {
+            Some(game) => {
+                let border_color = if self.is_drawing() {
+                    self.color.fit_to(self.color_depth).into()
+                } else {
+                    TuiColor::Reset
+                };
+                // show the shape being dragged out on top of the canvas
+                let preview_canvas;
+                let pixels = match self.shape_preview {
+                    Some(shape) => {
+                        let mut canvas = game.canvas.clone();
+                        self.shape_draw(shape).apply(&mut canvas, game.dimensions);
+                        preview_canvas = canvas;
+                        &preview_canvas
+                    }
+                    None => &game.canvas,
+                };
+                let canvas = CanvasWidget::new(
+                    pixels,
+                    game.dimensions,
+                    self.canvas_mode,
+                    self.color_depth,
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(border_color)),
+                );
+                frame.render_widget(canvas, layout.canvas);
+
+                if self.is_drawing() {
+                    frame.render_widget(
+                        PaletteWidget::new(self.color, self.color_depth, self.brush, self.tool),
+                        layout.palette,
+                    );
+
+                    if let Some(input) = &self.color_picker {
+                        ColorPicker::new(layout.canvas).draw(
+                            frame,
+                            input,
+                            self.color,
+                            self.color_depth,
+                        );
+                    }
+                }
+            }
+            None => {
+                let lobby = Paragraph::new(self.lobby_lines())
+                    .wrap(Wrap { trim: false })
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .title(format!("Room {}", self.info.key)),
+                    );
+                frame.render_widget(lobby, layout.canvas);
+            }
+        }
+
+        let players = List::new(self.player_lines())
+            .block(Block::default().borders(Borders::ALL).title("Players"));
+        frame.render_widget(players, layout.players);
+
+        frame.render_widget(
+            ChatWidget::new(&self.messages, Block::default().borders(Borders::ALL)),
+            layout.chat,
+        );
+
+        let input_title = if self.is_spectator {
+            "Your message (spectating)"
+        } else {
+            "Your message"
+        };
+        self.chat_input.draw(frame, layout.input, input_title);
+
+        self.draw_phase_overlay(frame, layout.canvas);
+
+        if let Some(choices) = self.word_choices() {
+            let remaining_secs = self
+                .game
+                .as_ref()
+                .map_or(0, GameInfo::remaining_secs_in_phase);
+
+            frame.render_widget(
+                WordPickerWidget::new(choices, self.selected_choice, remaining_secs),
+                layout.canvas,
+            );
+        }
+
+        draw_notifications(frame, app);
+    }
+
+    fn on_key_event(&mut self, event: KeyEvent) -> Action {
+        if let Some(choices) = self.word_choices() {
+            let num_of_choices = choices.len();
+
+            match event.code {
+                KeyCode::Up => self.selected_choice = self.selected_choice.saturating_sub(1),
+                KeyCode::Down => {
+                    self.selected_choice = (self.selected_choice + 1).min(num_of_choices - 1)
+                }
+                KeyCode::Enter => return self.choose_word(self.selected_choice),
+                KeyCode::Char(c) if c.is_ascii_digit() && c != '0' => {
+                    return self.choose_word(c as usize - '1' as usize)
+                }
+                _ => {}
+            };
+
+            return no_action();
+        }
+
+        if self.game.is_none() && self.is_leader() {
+            if let Some(action) = self.on_leader_key(event.code) {
+                return action;
+            }
+        }
+
+        if let Some(input) = &mut self.color_picker {
+            match event.code {
+                KeyCode::Enter => {
+                    let hex = input.take();
 
-pub fn draw<B: Backend>(app: &mut App, terminal: &mut Terminal<B>) -> Result<()> {
-    let dimensions = app.canvas.dimensions;
-    terminal.draw(|mut f| {
-        use Constraint::*;
-        let size = f.size();
-        let main_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .margin(0)
-            .constraints(
-                [
-                    Length(dimensions.0 as u16),
-                    Length(if size.width < dimensions.0 as u16 {
-                        size.width
-                    } else {
-                        size.width - dimensions.0 as u16
+                    if !hex.trim().is_empty() {
+                        match Color::from_hex(&hex) {
+                            Some(color) => self.color = color,
+                            None => return notify("Custom colors look like #ff8800"),
+                        }
+                    }
+                    self.color_picker = None;
+                }
+                KeyCode::F(11) => self.color_picker = None,
+                _ => {
+                    input.on_key(event);
+                }
+            };
+
+            return no_action();
+        }
+
+        let is_ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+
+        match event.code {
+            KeyCode::Enter => {
+                let msg = self.chat_input.take();
+
+                if msg.trim().is_empty() {
+                    no_action()
+                } else {
+                    send(ToServer::Chat(ChatMessage::User(
+                        self.username.clone(),
+                        msg,
+                    )))
+                }
+            }
+            KeyCode::Delete if self.is_drawing() => self.draw_on_canvas(Draw::Clear),
+            KeyCode::Char('z') if is_ctrl && self.is_drawing() => send(ToServer::Draw(Draw::Undo)),
+            KeyCode::Char('y') if is_ctrl && self.is_drawing() => send(ToServer::Draw(Draw::Redo)),
+            KeyCode::Char('s') if is_ctrl => match self.revealed_drawing() {
+                Some(drawing) => Box::new(move |app| app.save_drawing(drawing)),
+                None => notify("Drawings can be saved once their word is revealed"),
+            },
+            KeyCode::F(11) if self.is_drawing() => {
+                self.open_color_picker();
+                no_action()
+            }
+            KeyCode::Tab => {
+                self.tool = self.tool.next();
+                no_action()
+            }
+            KeyCode::PageUp => {
+                self.resize_brush(1);
+                no_action()
+            }
+            KeyCode::PageDown => {
+                self.resize_brush(-1);
+                no_action()
+            }
+            KeyCode::F(10) => {
+                self.toggle_brush_tip();
+                no_action()
+            }
+            KeyCode::F(9) => {
+                self.canvas_mode = match self.canvas_mode {
+                    CanvasMode::HalfBlock => CanvasMode::FullBlock,
+                    CanvasMode::FullBlock => CanvasMode::HalfBlock,
+                };
+                no_action()
+            }
+            _ => {
+                self.chat_input.on_key(event);
+                no_action()
+            }
+        }
+    }
+
+    fn on_mouse_event(&mut self, event: MouseEvent) -> Action {
+        let layout = self.layout(self.size);
+        let pos = (event.column, event.row);
+
+        if let Some(choices) = self.word_choices() {
+            if let MouseEventKind::Down(MouseButton::Left) = event.kind {
+                let picker = WordPickerWidget::new(choices, self.selected_choice, 0);
+                if let Some(idx) = picker.choice_at(layout.canvas, pos) {
+                    return self.choose_word(idx);
+                }
+            }
+
+            return no_action();
+        }
+
+        if !self.is_drawing() {
+            return no_action();
+        }
+
+        if self.color_picker.is_some() {
+            if let MouseEventKind::Down(MouseButton::Left) = event.kind {
+                let picker = ColorPicker::new(layout.canvas);
+
+                if let Some(color) = picker.color_at(pos) {
+                    self.color = color;
+                    self.color_picker = None;
+                } else if !contains(picker.popup, pos) {
+                    self.color_picker = None;
+                }
+            }
+
+            return no_action();
+        }
+
+        let canvas_view = match layout.canvas_view {
+            Some(view) => view,
+            None => return no_action(),
+        };
+        let cell = canvas_view.cell_at(pos);
+
+        match (event.kind, cell) {
+            (MouseEventKind::Down(MouseButton::Left), _) if contains(layout.palette, pos) => {
+                match PaletteWidget::control_at(layout.palette, pos) {
+                    Some(PaletteControl::Color(color)) => self.color = color,
+                    Some(PaletteControl::MoreColors) => self.open_color_picker(),
+                    Some(PaletteControl::Smaller) => self.resize_brush(-1),
+                    Some(PaletteControl::Larger) => self.resize_brush(1),
+                    Some(PaletteControl::Tip) => self.toggle_brush_tip(),
+                    Some(PaletteControl::Tool) => self.tool = self.tool.next(),
+                    None => {}
+                }
+                no_action()
+            }
+
+            (MouseEventKind::Down(MouseButton::Left), Some(cell)) if self.tool == Tool::Fill => {
+                match canvas_view.cell_points(cell).first() {
+                    Some(origin) => self.draw_on_canvas(Draw::Fill {
+                        origin: *origin,
+                        color: self.color,
                     }),
-                ]
-                .as_ref(),
-            )
-            .split(size);
+                    None => no_action(),
+                }
+            }
+
+            (MouseEventKind::Down(MouseButton::Left), Some(cell))
+            | (MouseEventKind::Drag(MouseButton::Left), Some(cell))
+                if self.tool == Tool::Brush =>
+            {
+                let cells = match self.last_cell {
+                    Some(last) if matches!(event.kind, MouseEventKind::Drag(_)) => {
+                        message::line_points(last, cell)
+                    }
+                    _ => vec![cell],
+                };
+
+                let mut points: Vec<Coord> = cells
+                    .into_iter()
+                    .flat_map(|cell| canvas_view.cell_points(cell))
+                    .collect();
+                points.sort_unstable();
+                points.dedup();
+
+                self.last_cell = Some(cell);
+                self.draw_on_canvas(Draw::Paint {
+                    points,
+                    color: self.color,
+                    brush: self.brush,
+                })
+            }
+
+            (MouseEventKind::Down(MouseButton::Right), Some(cell))
+            | (MouseEventKind::Drag(MouseButton::Right), Some(cell)) => {
+                self.last_cell = Some(cell);
+                let actions: Vec<Action> = canvas_view
+                    .cell_points(cell)
+                    .into_iter()
+                    .map(|point| self.draw_on_canvas(Draw::Erase(point)))
+                    .collect();
+
+                Box::new(move |app| actions.into_iter().for_each(|action| action(app)))
+            }
+
+            (MouseEventKind::Down(MouseButton::Left), Some(cell))
+            | (MouseEventKind::Drag(MouseButton::Left), Some(cell)) => {
+                if let Some(point) = canvas_view.cell_points(cell).first().copied() {
+                    let from = match self.shape_preview {
+                        Some(shape) if matches!(event.kind, MouseEventKind::Drag(_)) => shape.from,
+                        _ => point,
+                    };
+                    self.shape_preview = self.tool.shape(from, point);
+                }
+                no_action()
+            }
+
+            (MouseEventKind::Up(_), _) => {
+                if let Some(shape) = self.shape_preview.take() {
+                    self.draw_on_canvas(self.shape_draw(shape))
+                } else if self.last_cell.take().is_some() {
+                    // lets the server tell freehand strokes apart for undo
+                    send(ToServer::Draw(Draw::EndStroke))
+                } else {
+                    no_action()
+                }
+            }
+
+            _ => no_action(),
+        }
+    }
+
+    fn on_resize(&mut self, (width, height): (u16, u16)) {
+        self.size = Rect::new(0, 0, width, height);
+    }
+}
+
+/// seconds a game phase lasts, counted from when its state update arrives
+fn phase_secs(state: &GameState, draw_time: u64) -> u64 {
+    match state {
+        GameState::RoundStart(_) => ROUND_START_SECS,
+        GameState::Playing(turn) => match turn.phase {
+            TurnPhase::ChoosingWord(_) => CHOOSE_WORDS_TIME,
+            TurnPhase::Drawing(_) => draw_time,
+            TurnPhase::RevealWord { .. } => REVEAL_PHASE_SECS,
+        },
+        GameState::Finish => FINISH_SECS,
+    }
+}
+
+/// the word to draw, or the hint with unrevealed characters as `_`
+fn hint_text(hint: &WordHint) -> String {
+    match hint {
+        WordHint::Draw(word) => word.clone(),
+        WordHint::Hint { hints, word_len } => (0..*word_len)
+            .map(|idx| hints.get(&idx).copied().unwrap_or('_').to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+fn contains(area: Rect, (x, y): (u16, u16)) -> bool {
+    x >= area.x && x < area.x + area.width && y >= area.y && y < area.y + area.height
+}
+
+/// rect of the given size in the center of `area`
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// box with the given lines, centered over `area`
+fn draw_popup(frame: &mut Frame<Backend>, area: Rect, title: &str, lines: Vec<Spans>) {
+    let width = lines
+        .iter()
+        .map(Spans::width)
+        .chain(std::iter::once(title.len()))
+        .max()
+        .unwrap_or_default() as u16
+        + 4;
+    let popup = centered_rect(width, lines.len() as u16 + 2, area);
+
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+        popup,
+    );
+}
 
-        let canvas_widget = CanvasWidget::new(
-            &app.canvas,
+fn draw_notifications(frame: &mut Frame<Backend>, app: &App) {
+    let notifications = app.notifications();
+    if notifications.is_empty() {
+        return;
+    }
+
+    let lines = notifications
+        .iter()
+        .map(|notif| Spans::from(notif.as_str()))
+        .collect::<Vec<_>>();
+    let width = lines.iter().map(Spans::width).max().unwrap_or_default() as u16 + 4;
+    let size = frame.size();
+    let area = Rect {
+        y: 1,
+        ..centered_rect(width, lines.len() as u16 + 2, size)
+    };
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(app.current_color.into())),
-        );
+                .border_style(Style::default().fg(TuiColor::Red)),
+        ),
+        area,
+    );
+}
 
-        let game_state_height = app
-            .game_state
-            .as_ref()
-            .map(|x| x.player_states.len() + 3)
-            .unwrap_or(0) as u16;
+/// what a left click on the canvas does
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tool {
+    Brush,
+    Fill,
+    Line,
+    Rect,
+    FilledRect,
+    Ellipse,
+    FilledEllipse,
+}
 
-        let sidebar_chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .margin(0)
-            .constraints([Length(game_state_height), Percentage(100)].as_ref())
-            .split(main_chunks[1]);
+impl Tool {
+    const ALL: [Tool; 7] = [
+        Tool::Brush,
+        Tool::Fill,
+        Tool::Line,
+        Tool::Rect,
+        Tool::FilledRect,
+        Tool::Ellipse,
+        Tool::FilledEllipse,
+    ];
 
-        if let Some(skribbl_state) = app.game_state.as_mut() {
-            let skribbl_widget = SkribblStateWidget::new(
-                &skribbl_state,
-                &app.session.username,
-                app.remaining_time.unwrap_or(0),
-                Block::default().borders(Borders::NONE),
-            );
-            f.render_widget(skribbl_widget, sidebar_chunks[0]);
+    fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|tool| *tool == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Brush => "Brush",
+            Tool::Fill => "Fill",
+            Tool::Line => "Line",
+            Tool::Rect => "Rect",
+            Tool::FilledRect => "Rect ■",
+            Tool::Ellipse => "Ellipse",
+            Tool::FilledEllipse => "Ellipse ■",
         }
+    }
 
-        let canvas_rect = Rect {
-            height: u16::min(dimensions.1 as u16, main_chunks[0].height),
-            ..main_chunks[0]
+    /// shape dragged out from one point to another, if this is a shape tool
+    fn shape(self, from: Coord, to: Coord) -> Option<Shape> {
+        let (kind, filled) = match self {
+            Tool::Brush | Tool::Fill => return None,
+            Tool::Line => (ShapeKind::Line, false),
+            Tool::Rect => (ShapeKind::Rect, false),
+            Tool::FilledRect => (ShapeKind::Rect, true),
+            Tool::Ellipse => (ShapeKind::Ellipse, false),
+            Tool::FilledEllipse => (ShapeKind::Ellipse, true),
         };
-        f.render_widget(canvas_widget, canvas_rect);
 
-        let displayed_messages = (&app.chat.messages)
-            .iter()
-            .filter(|msg| match msg {
-                Message::SystemMsg(_) => true,
-                Message::UserMsg(username, _) => app.game_state.as_ref().map_or(true, |state| {
-                    app.is_drawing()
-                        || app.own_player().map_or(false, |x| x.has_solved)
-                        || (&state.drawing_user != username
-                            && !state
-                                .player_states
-                                .get(&username)
-                                .map_or(false, |player_state| player_state.has_solved))
-                }),
-            })
-            .collect::<Vec<_>>();
+        Some(Shape {
+            kind,
+            from,
+            to,
+            filled,
+        })
+    }
+}
+
+/// how canvas pixels are drawn onto terminal cells
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CanvasMode {
+    HalfBlock, // two pixels per cell, stacked with `▀`
+    FullBlock, // one pixel per cell
+}
+
+impl CanvasMode {
+    fn pixels_per_cell(self) -> u16 {
+        match self {
+            CanvasMode::HalfBlock => 2,
+            CanvasMode::FullBlock => 1,
+        }
+    }
+}
+
+/// Maps the canvas onto the terminal, scaled to fit while keeping its aspect ratio, so
+/// drawings look the same on every terminal size.
+#[derive(Debug, Copy, Clone)]
+struct CanvasView {
+    area: Rect,        // cells covered by the canvas
+    dimensions: Coord, // canvas size in pixels
+    mode: CanvasMode,
+    scale: f32, // terminal pixels per canvas pixel
+}
+
+impl CanvasView {
+    fn fit(available: Rect, dimensions: Coord, mode: CanvasMode) -> Self {
+        let pixels_per_cell = mode.pixels_per_cell() as f32;
+        let (width, height) = (dimensions.0.max(1) as f32, dimensions.1.max(1) as f32);
+        let scale = (available.width as f32 / width)
+            .min(available.height as f32 * pixels_per_cell / height);
 
-        let chat_widget = ChatWidget::new(
-            displayed_messages.as_slice(),
-            &app.chat.input,
-            Block::default().borders(Borders::NONE),
+        let area = Rect {
+            width: ((width * scale) as u16).min(available.width),
+            height: ((height * scale / pixels_per_cell).ceil() as u16).min(available.height),
+            ..available
+        };
+
+        Self {
+            area,
+            dimensions,
+            mode,
+            scale,
+        }
+    }
+
+    /// canvas pixel shown at a terminal pixel, relative to the canvas area
+    fn canvas_point(&self, (x, y): Coord) -> Option<Coord> {
+        let point = (
+            (x as f32 / self.scale) as u16,
+            (y as f32 / self.scale) as u16,
+        );
+        Some(point).filter(|(x, y)| *x < self.dimensions.0 && *y < self.dimensions.1)
+    }
+
+    /// cell at a terminal position relative to the canvas area, if on the canvas
+    fn cell_at(&self, pos: (u16, u16)) -> Option<Coord> {
+        if contains(self.area, pos) {
+            Some((pos.0 - self.area.x, pos.1 - self.area.y))
+        } else {
+            None
+        }
+    }
+
+    /// canvas pixels covered by a cell
+    fn cell_points(&self, (col, row): Coord) -> Vec<Coord> {
+        let pixels_per_cell = self.mode.pixels_per_cell();
+        let (x0, x1) = self.pixel_range(col, col + 1, self.dimensions.0);
+        let (y0, y1) = self.pixel_range(
+            row * pixels_per_cell,
+            (row + 1) * pixels_per_cell,
+            self.dimensions.1,
         );
-        f.render_widget(chat_widget, sidebar_chunks[1]);
-    })?;
-    Ok(())
+
+        (y0..y1)
+            .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+            .collect()
+    }
+
+    /// range of canvas pixels covered by the terminal pixels `from..to`, at least one
+    fn pixel_range(&self, from: u16, to: u16, max: u16) -> (u16, u16) {
+        let start = (from as f32 / self.scale) as u16;
+        let end = ((to as f32 / self.scale).ceil() as u16).max(start + 1);
+
+        (start.min(max), end.min(max))
+    }
 }
 
 pub struct CanvasWidget<'a, 't> {
     block: Block<'a>,
-    canvas: &'t AppCanvas,
+    canvas: &'t HashMap<Coord, Color>,
+    dimensions: Coord,
+    mode: CanvasMode,
+    depth: ColorDepth,
 }
 
 impl<'a, 't> CanvasWidget<'a, 't> {
-    pub fn new(canvas: &'t AppCanvas, block: Block<'a>) -> CanvasWidget<'a, 't> {
-        CanvasWidget { block, canvas }
+    pub fn new(
+        canvas: &'t HashMap<Coord, Color>,
+        dimensions: Coord,
+        mode: CanvasMode,
+        depth: ColorDepth,
+        block: Block<'a>,
+    ) -> CanvasWidget<'a, 't> {
+        CanvasWidget {
+            block,
+            canvas,
+            dimensions,
+            mode,
+            depth,
+        }
+    }
+}
+
+impl<'a, 't> Widget for CanvasWidget<'a, 't> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let view = CanvasView::fit(self.block.inner(area), self.dimensions, self.mode);
+        let canvas = view.area;
+
+        self.block.render(
+            Rect {
+                width: canvas.width + 2,
+                height: canvas.height + 2,
+                ..area
+            },
+            buf,
+        );
+
+        // empty pixels are white, anything past the canvas is left as is
+        let (pixels, depth) = (self.canvas, self.depth);
+        let pixel_color = |x, y| {
+            view.canvas_point((x, y)).map(|point| {
+                pixels
+                    .get(&point)
+                    .map_or(TuiColor::White, |color| color.fit_to(depth).into())
+            })
+        };
+
+        for row in 0..canvas.height {
+            for col in 0..canvas.width {
+                let cell = buf.get_mut(canvas.x + col, canvas.y + row);
+
+                match self.mode {
+                    CanvasMode::HalfBlock => {
+                        let top = pixel_color(col, row * 2).unwrap_or(TuiColor::Reset);
+                        let bottom = pixel_color(col, row * 2 + 1).unwrap_or(TuiColor::Reset);
+                        cell.set_symbol("▀").set_fg(top).set_bg(bottom);
+                    }
+                    CanvasMode::FullBlock => {
+                        cell.set_bg(pixel_color(col, row).unwrap_or(TuiColor::Reset));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// colors the terminal can show, guessed from the environment like most terminal apps do
+fn detect_color_depth() -> ColorDepth {
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let term = env::var("TERM").unwrap_or_default();
+
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        ColorDepth::TrueColor
+    } else if term.contains("256color") || term.contains("direct") {
+        ColorDepth::Ansi256
+    } else {
+        ColorDepth::Ansi16
+    }
+}
+
+/// Popup with every color of the 256-color palette, the ANSI colors on top, and an input for
+/// custom colors below
+struct ColorPicker {
+    popup: Rect,
+    grid: Rect,
+    input: Rect,
+    swatch_width: u16,
+}
+
+impl ColorPicker {
+    const COLUMNS: u16 = 36;
+    const ROWS: u16 = 8;
+
+    fn new(area: Rect) -> Self {
+        let swatch_width = if area.width >= Self::COLUMNS * 2 + 2 {
+            2
+        } else {
+            1
+        };
+        let popup = centered_rect(Self::COLUMNS * swatch_width + 2, Self::ROWS + 5, area);
+        let inner = Block::default().borders(Borders::ALL).inner(popup);
+        let grid = Rect {
+            height: Self::ROWS.min(inner.height),
+            ..inner
+        };
+        let input = Rect {
+            y: grid.y + grid.height,
+            height: inner.height - grid.height,
+            ..inner
+        };
+
+        Self {
+            popup,
+            grid,
+            input,
+            swatch_width,
+        }
+    }
+
+    /// color of the swatch in the given row and column of the grid
+    fn color(row: u16, col: u16) -> Option<Color> {
+        match row {
+            0 => Color::ANSI.get(col as usize).copied(),
+            1..=6 if col < 36 => Some(Color::Indexed(16 + (row as u8 - 1) * 36 + col as u8)),
+            7 if col < 24 => Some(Color::Indexed(232 + col as u8)),
+            _ => None,
+        }
+    }
+
+    fn color_at(&self, pos: (u16, u16)) -> Option<Color> {
+        if !contains(self.grid, pos) {
+            return None;
+        }
+
+        Self::color(
+            pos.1 - self.grid.y,
+            (pos.0 - self.grid.x) / self.swatch_width,
+        )
+    }
+
+    fn draw(&self, frame: &mut Frame<Backend>, input: &Input, selected: Color, depth: ColorDepth) {
+        frame.render_widget(Clear, self.popup);
+        frame.render_widget(
+            Block::default().borders(Borders::ALL).title("Colors"),
+            self.popup,
+        );
+        frame.render_widget(
+            ColorGridWidget {
+                selected,
+                depth,
+                swatch_width: self.swatch_width,
+            },
+            self.grid,
+        );
+        input.draw(frame, self.input, "Custom color #rrggbb");
     }
 }
 
-impl<'a, 't, 'b> Widget for CanvasWidget<'a, 't> {
-    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
-        self.block.render(area, buf);
-        let area = self.block.inner(area);
+struct ColorGridWidget {
+    selected: Color,
+    depth: ColorDepth,
+    swatch_width: u16,
+}
+
+impl Widget for ColorGridWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for row in 0..area.height {
+            for x in 0..area.width {
+                if let Some(color) = ColorPicker::color(row, x / self.swatch_width) {
+                    let cell = buf.get_mut(area.x + x, area.y + row);
+                    cell.set_bg(color.fit_to(self.depth).into());
 
-        for line in self.canvas.lines.iter() {
-            for cell in line.coords_in() {
-                if cell.within(
-                    &Coord(area.x, area.y),
-                    &Coord(area.x + area.width, area.y + area.height),
-                ) {
-                    buf.get_mut(cell.0, cell.1).set_bg(line.color.into());
+                    if color == self.selected {
+                        let (r, g, b) = color.rgb();
+                        let marker = if r as u16 + g as u16 + b as u16 > 384 {
+                            TuiColor::Black
+                        } else {
+                            TuiColor::White
+                        };
+                        cell.set_symbol("•").set_fg(marker);
+                    }
                 }
             }
         }
-        let swatch_size = area.width / self.canvas.palette.len() as u16;
-        for (idx, col) in self.canvas.palette.iter().enumerate() {
-            for offset in 0..swatch_size {
-                buf.get_mut(offset + (idx as u16 * swatch_size), 0)
-                    .set_bg((*col).into());
+    }
+}
+
+/// something the drawing player can click on in the palette bar
+pub enum PaletteControl {
+    Color(Color),
+    MoreColors,
+    Smaller,
+    Larger,
+    Tip,
+    Tool,
+}
+
+/// row of color swatches followed by the brush controls and the tool, the selected color is
+/// marked
+pub struct PaletteWidget {
+    selected: Color,
+    depth: ColorDepth,
+    brush: Brush,
+    tool: Tool,
+}
+
+impl PaletteWidget {
+    /// width of the controls at the right end of the bar, ` ▦ [-]● 1[+] Brush    `
+    const CONTROLS_WIDTH: u16 = 22;
+
+    pub fn new(selected: Color, depth: ColorDepth, brush: Brush, tool: Tool) -> Self {
+        Self {
+            selected,
+            depth,
+            brush,
+            tool,
+        }
+    }
+
+    fn swatches_width(area: Rect) -> u16 { area.width.saturating_sub(Self::CONTROLS_WIDTH) }
+
+    fn swatch_width(area: Rect) -> u16 {
+        (Self::swatches_width(area) / PALETTE.len() as u16).max(1)
+    }
+
+    /// control at the given terminal position, if any
+    pub fn control_at(area: Rect, (x, _): (u16, u16)) -> Option<PaletteControl> {
+        let x = x - area.x;
+        let swatches_width = Self::swatches_width(area);
+
+        if x < swatches_width {
+            let idx = x / Self::swatch_width(area);
+            return PALETTE
+                .get(idx as usize)
+                .copied()
+                .map(PaletteControl::Color);
+        }
+
+        match x - swatches_width {
+            1 => Some(PaletteControl::MoreColors),
+            3..=5 => Some(PaletteControl::Smaller),
+            6 => Some(PaletteControl::Tip),
+            9..=11 => Some(PaletteControl::Larger),
+            12..=21 => Some(PaletteControl::Tool),
+            _ => None,
+        }
+    }
+}
+
+impl Widget for PaletteWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let swatch_width = Self::swatch_width(area);
+        let swatches_width = Self::swatches_width(area);
+
+        for (idx, color) in PALETTE.iter().enumerate() {
+            let x = area.x + idx as u16 * swatch_width;
+
+            for offset in 0..swatch_width {
+                if x + offset >= area.x + swatches_width {
+                    break;
+                }
+
+                let cell = buf.get_mut(x + offset, area.y);
+                cell.set_bg(color.fit_to(self.depth).into());
+
+                if *color == self.selected {
+                    let marker = if *color == Color::Black {
+                        TuiColor::White
+                    } else {
+                        TuiColor::Black
+                    };
+                    cell.set_symbol("•").set_fg(marker);
+                }
             }
         }
+
+        let tip = match self.brush.tip {
+            BrushTip::Round => '●',
+            BrushTip::Square => '■',
+        };
+        let diameter = self.brush.radius as u16 * 2 + 1;
+
+        buf.set_stringn(
+            area.x + swatches_width,
+            area.y,
+            format!(" ▦ [-]{}{:>2}[+] {:<9}", tip, diameter, self.tool.label()),
+            Self::CONTROLS_WIDTH as usize,
+            Style::default(),
+        );
+
+        // the color picker button shows the selected color, which may not be a swatch
+        if swatches_width + 1 < area.width {
+            buf.get_mut(area.x + swatches_width + 1, area.y)
+                .set_fg(self.selected.fit_to(self.depth).into());
+        }
     }
 }
 
 pub struct ChatWidget<'a, 't> {
     block: Block<'a>,
-    messages: &'t [&'t Message],
-    input: &'t str,
+    messages: &'t [ChatMessage],
 }
 
 impl<'a, 't> ChatWidget<'a, 't> {
-    pub fn new(messages: &'t [&Message], input: &'t str, block: Block<'a>) -> ChatWidget<'a, 't> {
-        ChatWidget {
-            block,
-            messages,
-            input,
-        }
+    pub fn new(messages: &'t [ChatMessage], block: Block<'a>) -> ChatWidget<'a, 't> {
+        ChatWidget { block, messages }
     }
 }
-impl<'a, 't, 'b> Widget for ChatWidget<'a, 't> {
-    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
-        use Constraint::*;
-        self.block.render(area, buf);
-        let area = self.block.inner(area);
 
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .margin(0)
-            .constraints([Length(3), Percentage(100)].as_ref())
-            .split(area);
-
-        Paragraph::new([Text::Raw(self.input.clone().into())].iter())
-            .block(Block::default().borders(Borders::ALL).title("Your message"))
-            .render(chunks[0], buf);
+impl<'a, 't> Widget for ChatWidget<'a, 't> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = self.block.title("Chat");
+        let inner = block.inner(area);
+        let width = (inner.width as usize).max(1);
 
-        List::new(self.messages.iter().rev().map(|msg| {
-            Text::styled(
-                format!("{}", msg),
-                if msg.is_system() {
-                    Style::default().fg(Color::Cyan)
+        // wrap long messages, newest at the bottom
+        let mut lines = self
+            .messages
+            .iter()
+            .flat_map(|msg| {
+                let style = if msg.is_system() {
+                    Style::default().fg(TuiColor::Cyan)
                 } else {
                     Style::default()
-                },
-            )
-        }))
-        .block(Block::default().borders(Borders::ALL).title("Chat"))
-        .render(chunks[1], buf);
+                };
+
+                let chars: Vec<char> = msg.to_string().chars().collect();
+                chars
+                    .chunks(width)
+                    .map(|line| ListItem::new(Span::styled(line.iter().collect::<String>(), style)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let skip = lines.len().saturating_sub(inner.height as usize);
+        lines.drain(..skip);
+
+        List::new(lines).block(block).render(area, buf);
     }
 }
 
-pub struct SkribblStateWidget<'a, 't> {
-    block: Block<'a>,
-    state: &'t SkribblState,
-    username: &'t Username,
-    remaining_time: u32,
+/// overlay that lets the drawing player pick a word to draw
+pub struct WordPickerWidget<'t> {
+    choices: &'t [String],
+    selected: usize,
+    remaining_secs: u64,
 }
-impl<'a, 't> SkribblStateWidget<'a, 't> {
-    pub fn new(
-        state: &'t SkribblState,
-        username: &'t Username,
-        remaining_time: u32,
-        block: Block<'a>,
-    ) -> SkribblStateWidget<'a, 't> {
-        SkribblStateWidget {
-            block,
-            state,
-            username,
-            remaining_time,
+
+impl<'t> WordPickerWidget<'t> {
+    pub fn new(choices: &'t [String], selected: usize, remaining_secs: u64) -> Self {
+        Self {
+            choices,
+            selected,
+            remaining_secs,
         }
     }
-}
 
-impl<'a, 't, 'b> Widget for SkribblStateWidget<'a, 't> {
-    fn render(self, area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
-        self.block.render(area, buf);
-        let area = self.block.inner(area);
+    /// area the picker occupies when drawn over `area`
+    pub fn area(&self, area: Rect) -> Rect {
+        let width = self
+            .choices
+            .iter()
+            .map(|word| word.len() as u16 + 6)
+            .max()
+            .unwrap_or(0)
+            .max(28)
+            .min(area.width);
+        let height = (self.choices.len() as u16 + 2).min(area.height);
 
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .margin(0)
-            .constraints([Constraint::Length(1), Constraint::Percentage(100)].as_ref())
-            .split(area);
+        Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        }
+    }
 
-        let is_drawing = self.state.drawing_user == *self.username;
+    /// index of the choice at the given terminal position, if any
+    pub fn choice_at(&self, area: Rect, (x, y): (u16, u16)) -> Option<usize> {
+        let area = self.area(area);
+        let inner = Block::default().borders(Borders::ALL).inner(area);
 
-        let current_word_representation = if is_drawing {
-            self.state.current_word().to_string()
+        if x >= inner.x && x < inner.x + inner.width && y >= inner.y {
+            Some((y - inner.y) as usize).filter(|idx| *idx < self.choices.len())
         } else {
-            self.state.hinted_current_word().to_string()
-        };
+            None
+        }
+    }
+}
+
+impl<'t> Widget for WordPickerWidget<'t> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = self.area(area);
+        Clear.render(area, buf);
 
-        Paragraph::new(
-            [Text::Styled(
-                format!(
-                    "{} drawing {}",
-                    self.state.drawing_user, current_word_representation
-                )
-                .into(),
-                if is_drawing {
-                    Style::default().bg(Color::Red)
+        let items = self
+            .choices
+            .iter()
+            .enumerate()
+            .map(|(idx, word)| {
+                let style = if idx == self.selected {
+                    Style::default().fg(TuiColor::Black).bg(TuiColor::Yellow)
                 } else {
                     Style::default()
-                },
-            )]
-            .iter(),
-        )
-        .render(chunks[0], buf);
+                };
 
-        let mut sorted_player_entries = self
-            .state
-            .player_states
-            .iter()
-            .collect::<Vec<(&Username, &PlayerState)>>();
-        sorted_player_entries.sort_by_key(|(x, _)| *x);
-
-        List::new(
-            sorted_player_entries
-                .into_iter()
-                .map(|(username, player_state)| {
-                    Text::styled(
-                        format!("{}: {}", username, player_state.score,),
-                        if self.state.drawing_user == *username {
-                            Style::default().bg(tui::style::Color::Cyan)
-                        } else if self.state.has_solved(username) {
-                            Style::default().fg(tui::style::Color::Green)
-                        } else {
-                            Style::default()
-                        },
-                    )
-                }),
-        )
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title(&format!("Players [time: {}]", self.remaining_time)),
-        )
-        .render(chunks[1], buf);
+                ListItem::new(Span::styled(format!(" {}. {}", idx + 1, word), style))
+            })
+            .collect::<Vec<_>>();
+
+        List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Choose a word [time: {}]", self.remaining_secs)),
+            )
+            .render(area, buf);
     }
 }
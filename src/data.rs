@@ -10,7 +10,9 @@ use tui::style::Color as TuiColor;
 
 use crate::utils;
 
-pub type UserId = u8;
+/// Session id of a connected user, ids are handed out in order so a disconnected player's id
+/// isn't reused while a room may still keep their place
+pub type UserId = u32;
 
 #[derive(Default, Eq, Clone, Serialize, Deserialize, Ord, PartialOrd)]
 pub struct Username(String, UserId);
@@ -60,15 +62,49 @@ pub struct GameOpts {
     pub draw_time: usize,
     pub custom_words: Vec<String>,
     pub only_custom_words: bool,
+    pub custom_words_ratio: u8, // chance in percent of offering a custom word over a server word
+    pub word_packs: Vec<String>, // names of server word packs to use, all packs if empty
+    pub game_mode: GameMode,
+    pub scoring: Scoring,
     // pub canvas_bg_color: Color,
 }
 
+/// How players are grouped in a game
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    FreeForAll,
+    Teams(usize), // number of players per team
+}
+
+/// How points are handed out at the end of a turn
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scoring {
+    #[default]
+    Classic, // quick and early guesses are worth more
+    Linear, // points go down linearly with time
+    Flat,   // every correct guess is worth the same
+}
+
+/// Description of a word pack available on the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WordPackInfo {
+    pub name: String,
+    pub language: String,
+    pub categories: Vec<String>,
+    pub num_of_words: usize,
+}
+
+/// Index of a team in a team game
+pub type TeamId = usize;
+
 /// The data server stores for every player in a game
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PlayerData {
     pub name: Username,
     pub score: usize,
     pub secs_to_solve_turn: u64,
+    pub team: Option<TeamId>,
 }
 
 impl From<Username> for PlayerData {
@@ -77,6 +113,7 @@ impl From<Username> for PlayerData {
             name,
             score: 0,
             secs_to_solve_turn: 0,
+            team: None,
         }
     }
 }
@@ -110,7 +147,7 @@ impl From<&str> for WordHint {
 impl WordHint {
     pub fn to_draw(&self) -> Option<&String> {
         match self {
-            WordHint::Draw(word) => Some(&word),
+            WordHint::Draw(word) => Some(word),
             WordHint::Hint { .. } => None,
         }
     }
@@ -119,7 +156,7 @@ impl WordHint {
 /// list of states a turn could be in
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum TurnPhase {
-    ChoosingWord(Vec<String>), // words to choose from, TODO: hide this from players
+    ChoosingWord(Vec<String>), // words to choose from, only sent to the player drawing
     Drawing(WordHint),
     RevealWord {
         word: String,
@@ -180,6 +217,7 @@ pub struct GameInfo {
     pub next_phase_timestamp: u64,
     pub num_of_rounds: usize,
     pub players: Vec<PlayerData>,
+    #[serde(with = "crate::canvas")] // sent as a compact bitmap, see `canvas`
     pub canvas: HashMap<Coord, Color>, // map of coord-color pairs sent to the server.
 }
 
@@ -192,6 +230,31 @@ impl GameInfo {
         self.players.iter_mut().find(|p| p.name.id() == id)
     }
 
+    pub fn is_team_game(&self) -> bool { self.players.iter().any(|p| p.team.is_some()) }
+
+    /// whether both players are in the same team, always false in free-for-all
+    pub fn are_teammates(&self, a: UserId, b: UserId) -> bool {
+        let team_of = |id| self.get_player(id).and_then(|p| p.team);
+        team_of(a).is_some() && team_of(a) == team_of(b)
+    }
+
+    /// combined score of each team, ordered by team
+    pub fn team_scores(&self) -> Vec<(TeamId, usize)> {
+        let mut scores: Vec<(TeamId, usize)> = Vec::new();
+
+        for player in self.players.iter() {
+            if let Some(team) = player.team {
+                match scores.iter_mut().find(|(t, _)| *t == team) {
+                    Some((_, score)) => *score += player.score,
+                    None => scores.push((team, player.score)),
+                }
+            }
+        }
+
+        scores.sort_by_key(|(team, _)| *team);
+        scores
+    }
+
     pub fn who_is_drawing(&self) -> Option<&Username> {
         self.state.as_turn().and_then(|t| {
             self.players
@@ -213,17 +276,19 @@ impl GameInfo {
 
 macro_rules! derive_into {
     ($(#[$meta:meta])*
-       $vis:vis enum $name: ident => $into_type: path { $($variant: ident => $into_variant: expr,)* }
+       $vis:vis enum $name: ident => $into_type: path {
+           $($variant: ident $(($($field: ident: $field_type: ty),*))? => $into_variant: expr,)*
+       }
      ) => {
         $(#[$meta])*
         $vis enum $name {
-            $($variant),*
+            $($variant $(($($field_type),*))?),*
         }
 
         impl From<$name> for $into_type {
             fn from(v: $name) -> Self {
                 match v {
-                    $($name::$variant => $into_variant,)*
+                    $($name::$variant $(($($field),*))? => $into_variant,)*
                 }
             }
         }
@@ -249,5 +314,133 @@ derive_into! {
         LightCyan => TuiColor::LightCyan,
         Magenta => TuiColor::Magenta,
         LightMagenta => TuiColor::LightMagenta,
+        Indexed(index: u8) => TuiColor::Indexed(index), // xterm 256-color palette
+        Rgb(r: u8, g: u8, b: u8) => TuiColor::Rgb(r, g, b),
     }
 }
+
+/// How many colors a terminal can show
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl Color {
+    /// the 16 ANSI colors, in the order of their palette index
+    pub const ANSI: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+
+    /// xterm's default values of the ANSI colors
+    const ANSI_RGB: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+
+    /// channel values of the 6x6x6 color cube in the 256-color palette
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    pub fn rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(index @ 0..=15) => Self::ANSI_RGB[index as usize],
+            Color::Indexed(index @ 16..=231) => {
+                let index = (index - 16) as usize;
+                let level = |n: usize| Self::CUBE_LEVELS[n % 6];
+                (level(index / 36), level(index / 6), level(index))
+            }
+            Color::Indexed(index) => {
+                let gray = 8 + (index - 232) * 10;
+                (gray, gray, gray)
+            }
+            ansi => Self::ANSI_RGB[Self::ANSI.iter().position(|c| *c == ansi).unwrap_or(0)],
+        }
+    }
+
+    /// closest color a terminal with the given color depth can show
+    pub fn fit_to(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (_, ColorDepth::TrueColor) => self,
+            (Color::Rgb(..), ColorDepth::Ansi256) => self.nearest_indexed(),
+            (Color::Indexed(index @ 0..=15), ColorDepth::Ansi16) => Self::ANSI[index as usize],
+            (Color::Indexed(_), ColorDepth::Ansi16) | (Color::Rgb(..), ColorDepth::Ansi16) => {
+                let rgb = self.rgb();
+                Self::ANSI
+                    .iter()
+                    .copied()
+                    .min_by_key(|ansi| distance(ansi.rgb(), rgb))
+                    .unwrap_or(self)
+            }
+            _ => self,
+        }
+    }
+
+    /// closest color of the color cube or the gray ramp of the 256-color palette
+    fn nearest_indexed(self) -> Color {
+        let (r, g, b) = self.rgb();
+        let nearest_level = |value: u8| {
+            (0..6)
+                .min_by_key(|level| (Self::CUBE_LEVELS[*level] as i32 - value as i32).abs())
+                .unwrap_or(0) as u8
+        };
+        let cube =
+            Color::Indexed(16 + 36 * nearest_level(r) + 6 * nearest_level(g) + nearest_level(b));
+
+        let average = (r as u16 + g as u16 + b as u16) / 3;
+        let gray = Color::Indexed(232 + (average.saturating_sub(3) / 10).min(23) as u8);
+
+        [cube, gray]
+            .iter()
+            .copied()
+            .min_by_key(|color| distance(color.rgb(), (r, g, b)))
+            .unwrap_or(cube)
+    }
+
+    /// parse a `#rrggbb` hex color, the `#` is optional
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let hex = hex.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+/// squared euclidean distance of two rgb colors
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
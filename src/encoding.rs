@@ -8,6 +8,7 @@ use tokio_util::codec::{Decoder, Encoder};
 
 pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("could not de/serialze")]
     Serialization(#[from] bincode::Error),
@@ -110,7 +111,7 @@ where
             return Ok(None);
         }
 
-        src.advance(header_size as usize);
+        src.advance(header_size);
         let data = &src.split_to(payload_size).freeze();
 
         Ok(Some(bincode::deserialize(data)?))
@@ -50,15 +50,13 @@ where
         }
     }
 
-    fn selector(&self) -> Selector<E> {
+    fn selector(&self) -> Selector<'_, E> {
         Selector::new()
             .recv(&self.urgent_recv, |v| v.unwrap())
             .recv(&self.recv, |v| v.unwrap())
     }
 
-    fn next_instant(&self) -> Option<Instant> {
-        self.timers.iter().map(|(instant, _)| *instant).next()
-    }
+    fn next_instant(&self) -> Option<Instant> { self.timers.keys().next().copied() }
 
     fn next_timed_event(&mut self) -> Option<E> {
         self.next_instant().and_then(|instant| {
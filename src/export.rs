@@ -0,0 +1,304 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    iter,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::data::{Color, Coord};
+
+/// color of pixels that were never painted
+const BACKGROUND: (u8, u8, u8) = (255, 255, 255);
+
+/// file formats a finished drawing can be saved as
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Svg,
+    /// truecolor half-block art, `cat` it in a terminal
+    Ansi,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Ansi => "ans",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(ExportFormat::Png),
+            "svg" => Ok(ExportFormat::Svg),
+            "ansi" => Ok(ExportFormat::Ansi),
+            _ => Err("expected one of png, svg or ansi".to_owned()),
+        }
+    }
+}
+
+/// a finished drawing and what it was of
+#[derive(Debug, Clone)]
+pub struct Drawing {
+    pub key: String,
+    pub round: usize,
+    pub word: String,
+    pub dimensions: Coord,
+    pub canvas: HashMap<Coord, Color>,
+}
+
+impl Drawing {
+    /// `<room key>-round<round>-<word>.<extension>`, the key and the word come from the server
+    /// and are kept to letters and digits
+    pub fn file_name(&self, format: ExportFormat) -> String {
+        format!(
+            "{}-round{}-{}.{}",
+            sanitize(&self.key),
+            self.round,
+            sanitize(&self.word),
+            format.extension()
+        )
+    }
+
+    fn pixel(&self, point: Coord) -> (u8, u8, u8) {
+        self.canvas
+            .get(&point)
+            .map_or(BACKGROUND, |color| color.rgb())
+    }
+}
+
+/// lowercase letters and digits, anything else becomes `_`
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect()
+}
+
+/// directory finished drawings are saved to
+#[derive(Debug, Clone, Default)]
+pub struct Gallery {
+    pub dir: PathBuf,
+    pub format: ExportFormat,
+}
+
+impl Gallery {
+    /// write the drawing into the gallery, never replacing an earlier drawing of the same name
+    pub fn save(&self, drawing: &Drawing) -> io::Result<PathBuf> {
+        let rendered = render(drawing, self.format)?;
+        fs::create_dir_all(&self.dir)?;
+
+        let (mut file, path) = create_free(&self.dir, &drawing.file_name(self.format))?;
+        file.write_all(&rendered)?;
+
+        Ok(path)
+    }
+}
+
+/// create `name`, or `name` with the first free counter before the extension, never replaces a
+/// file
+fn create_free(dir: &Path, name: &str) -> io::Result<(File, PathBuf)> {
+    let (stem, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+    let names =
+        iter::once(name.to_owned()).chain((2..).map(|n| format!("{}-{}.{}", stem, n, extension)));
+
+    for path in names.map(|name| dir.join(name)) {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!("endless counter")
+}
+
+pub fn render(drawing: &Drawing, format: ExportFormat) -> io::Result<Vec<u8>> {
+    match format {
+        ExportFormat::Png => render_png(drawing),
+        ExportFormat::Svg => Ok(render_svg(drawing).into_bytes()),
+        ExportFormat::Ansi => Ok(render_ansi(drawing).into_bytes()),
+    }
+}
+
+/// 8-bit RGB png
+fn render_png(drawing: &Drawing) -> io::Result<Vec<u8>> {
+    let (width, height) = drawing.dimensions;
+
+    let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = drawing.pixel((x, y));
+            data.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    drop(writer); // writes the end of the image
+
+    Ok(png)
+}
+
+/// one `rect` per horizontal run of a color on a white background
+fn render_svg(drawing: &Drawing) -> String {
+    let (width, height) = drawing.dimensions;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" shape-rendering=\"crispEdges\">\n",
+        width as u32 * 8,
+        height as u32 * 8,
+        width,
+        height
+    );
+    let _ = writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+        width,
+        height,
+        hex(BACKGROUND)
+    );
+
+    for y in 0..height {
+        let mut x = 0;
+        while x < width {
+            let color = drawing.canvas.get(&(x, y));
+            let run = (x..width)
+                .take_while(|x| drawing.canvas.get(&(*x, y)) == color)
+                .count() as u16;
+
+            if let Some(color) = color {
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"1\" fill=\"{}\"/>",
+                    x,
+                    y,
+                    run,
+                    hex(color.rgb())
+                );
+            }
+            x += run;
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// two pixels per character, the top one as foreground of `▀` and the bottom one as background
+fn render_ansi(drawing: &Drawing) -> String {
+    let (width, height) = drawing.dimensions;
+    let mut art = String::new();
+
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let (r, g, b) = drawing.pixel((x, y));
+            let _ = write!(art, "\x1b[38;2;{};{};{}m", r, g, b);
+
+            if y + 1 < height {
+                let (r, g, b) = drawing.pixel((x, y + 1));
+                let _ = write!(art, "\x1b[48;2;{};{};{}m", r, g, b);
+            }
+            art.push('▀');
+        }
+        art.push_str("\x1b[0m\n");
+    }
+
+    art
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String { format!("#{:02x}{:02x}{:02x}", r, g, b) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drawing(canvas: Vec<(Coord, Color)>) -> Drawing {
+        Drawing {
+            key: "abcde".to_owned(),
+            round: 2,
+            word: "Ice cream!".to_owned(),
+            dimensions: (4, 3),
+            canvas: canvas.into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let png = render(&drawing(vec![((0, 0), Color::Black)]), ExportFormat::Png).unwrap();
+
+        let (info, mut reader) = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        assert_eq!((info.width, info.height), (4, 3));
+        assert_eq!(info.color_type, png::ColorType::RGB);
+
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(&pixels[..6], &[0, 0, 0, 255, 255, 255]);
+        assert!(pixels[3..].iter().all(|&channel| channel == 255));
+    }
+
+    #[test]
+    fn svg_merges_runs() {
+        let svg = render_svg(&drawing(vec![
+            ((1, 1), Color::Rgb(255, 136, 0)),
+            ((2, 1), Color::Rgb(255, 136, 0)),
+        ]));
+
+        assert!(svg.contains("<rect x=\"1\" y=\"1\" width=\"2\" height=\"1\" fill=\"#ff8800\"/>"));
+        assert_eq!(svg.matches("<rect").count(), 2);
+    }
+
+    #[test]
+    fn file_names() {
+        let drawing = drawing(Vec::new());
+        assert_eq!(
+            drawing.file_name(ExportFormat::Ansi),
+            "abcde-round2-ice_cream_.ans"
+        );
+    }
+
+    #[test]
+    fn file_names_stay_in_their_directory() {
+        let drawing = Drawing {
+            key: "../../x".to_owned(),
+            ..drawing(Vec::new())
+        };
+        assert_eq!(
+            drawing.file_name(ExportFormat::Png),
+            "______x-round2-ice_cream_.png"
+        );
+    }
+
+    #[test]
+    fn saving_never_replaces_a_drawing() {
+        let dir = tempfile::tempdir().unwrap();
+        let gallery = Gallery {
+            dir: dir.path().to_owned(),
+            format: ExportFormat::Svg,
+        };
+        let drawing = drawing(vec![((0, 0), Color::Black)]);
+
+        let first = gallery.save(&drawing).unwrap();
+        fs::write(dir.path().join("abcde-round2-ice_cream_-2.svg"), "taken").unwrap();
+        let second = gallery.save(&drawing).unwrap();
+
+        assert_eq!(first, dir.path().join("abcde-round2-ice_cream_.svg"));
+        assert_eq!(second, dir.path().join("abcde-round2-ice_cream_-3.svg"));
+        assert_eq!(fs::read(&second).unwrap(), fs::read(&first).unwrap());
+    }
+}
@@ -1,21 +1,26 @@
 #![allow(dead_code, unused_variables)]
+mod canvas;
 mod client;
 mod data;
 mod encoding;
 mod events;
+mod export;
 mod message;
+mod replay;
 mod server;
 mod utils;
+mod websocket;
 
-use client::App;
+use client::{App, ReplayPlayer, ServerAddr};
 use data::GameOpts;
 use events::EventSender;
-use server::GameServer;
+use replay::Replay;
+use server::{GameServer, ProfileStore};
 use utils::dispatch_abortable_task;
 
 use argh::FromArgs;
 
-use std::{error::Error, net::SocketAddr};
+use std::error::Error;
 
 /// A Skribbl.io-alike for the terminal
 #[derive(FromArgs)]
@@ -32,6 +37,7 @@ struct Opt {
 enum SubOpt {
     Client(client::CliOpts),
     Server(server::CliOpts),
+    Replay(client::ReplayOpts),
 }
 
 async fn process_ctrl_c(tx: EventSender<server::Message>) {
@@ -53,12 +59,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
     match cmd {
         SubOpt::Client(opt) => {
             let mut app = App::default();
+            app.set_export(opt.export_gallery());
+
+            if let Some(path) = &opt.profile {
+                app.set_profile(client::load_profile(path)?);
+            }
             let localhost = opt.port.map(|port| format!("127.0.0.1:{}", port));
 
             if let Some(addr) = opt.host.or(localhost) {
                 app.set_host_input(addr.clone());
 
-                if let Ok(addr) = addr.parse::<SocketAddr>() {
+                if let Some(addr) = ServerAddr::resolve(&addr) {
                     app.connect_to_server(addr);
                 }
             }
@@ -70,7 +81,23 @@ async fn main() -> Result<(), Box<dyn Error>> {
             app.start().await?;
         }
 
-        SubOpt::Server(opts) => {
+        SubOpt::Replay(opt) => {
+            let replay = Replay::load(&opt.file)?;
+            let mut player = ReplayPlayer::new(replay, opt.speed);
+
+            if let Some(round) = opt.round {
+                player.seek_round(round);
+            }
+            if let Some(turn) = opt.turn {
+                player.seek_turn(turn);
+            }
+
+            let mut app = App::default();
+            app.play_replay(player);
+            app.start().await?;
+        }
+
+        SubOpt::Server(mut opts) => {
             let log_level_filter = if cli.log_debug {
                 log::LevelFilter::Debug
             } else {
@@ -95,17 +122,33 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 });
             }
 
-            let mut default_game_opts: GameOpts = opts.into();
-            let default_words = default_game_opts.custom_words.drain(..).collect();
-            let server = GameServer::new(default_game_opts, default_words);
-            let addr = format!("127.0.0.1:{}", port);
+            let word_packs = opts.take_word_packs();
+            let gallery = opts.gallery();
+            let replays = opts.replays.take();
+            let max_users = opts.max_users;
+            let addrs = opts.bind_addrs();
+            let ws_addrs = opts.websocket_addrs();
+            let profiles = match opts.profiles.take() {
+                Some(path) => Some(ProfileStore::open(&path)?),
+                None => None,
+            };
+            let default_game_opts: GameOpts = opts.into();
+            let server = GameServer::new(
+                default_game_opts,
+                word_packs,
+                gallery,
+                replays,
+                profiles,
+                max_users,
+            );
 
             // listen for ctrl_c
             let ctrlc_abort_handle =
                 dispatch_abortable_task(process_ctrl_c(server.sender().clone()));
 
-            println!("🚀 Running Termibbl server on port {}...", port);
-            server.listen_on(&addr).await?;
+            let addr_list: Vec<String> = addrs.iter().map(ToString::to_string).collect();
+            println!("🚀 Running Termibbl server on {}...", addr_list.join(", "));
+            server.listen_on(&addrs, &ws_addrs).await?;
             ctrlc_abort_handle.abort();
         }
     };
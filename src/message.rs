@@ -1,44 +1,459 @@
-use std::fmt::{self, Debug};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Debug},
+};
 
+use line_drawing::Bresenham;
 use serde::{Deserialize, Serialize};
 
-use crate::data::{Color, Coord, GameInfo, GameOpts, GameState, PlayerData, Username};
+use crate::data::{
+    Color, Coord, GameInfo, GameOpts, GameState, PlayerData, UserId, Username, WordPackInfo,
+};
 
 /// number of seconds between each heartbeat sent by client
 pub const HEARTBEAT_INTERVAL: u64 = 4;
 
+/// number of milliseconds paints are collected for before they are sent on, by the client and the
+/// room
+pub const DRAW_BATCH_MILLIS: u64 = 40;
+
+/// identifies a Termibbl peer in the handshake
+pub const PROTOCOL_MAGIC: [u8; 4] = *b"TRMB";
+
+/// version of the wire protocol, bump on any incompatible change to the messages
+pub const PROTOCOL_VERSION: u16 = 4;
+
+/// Optional protocol features a peer supports, none are defined yet but the handshake keeps
+/// room for them so adding one needs no version bump
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Features(u32);
+
+impl Features {
+    /// features supported by this build
+    pub const SUPPORTED: Features = Features(0);
+}
+
+/// The first frame sent in each direction of a connection
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Handshake {
+    pub magic: [u8; 4],
+    pub version: u16,
+    pub features: Features,
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self {
+            magic: PROTOCOL_MAGIC,
+            version: PROTOCOL_VERSION,
+            features: Features::SUPPORTED,
+        }
+    }
+}
+
+impl Handshake {
+    /// reason why a peer with this handshake can't be talked to, if any
+    pub fn incompatibility(&self) -> Option<String> {
+        if self.magic != PROTOCOL_MAGIC {
+            Some("Peer is not running Termibbl".to_owned())
+        } else if self.version != PROTOCOL_VERSION {
+            Some(format!(
+                "Incompatible Termibbl version, expected protocol v{} but got v{}",
+                PROTOCOL_VERSION, self.version
+            ))
+        } else {
+            None
+        }
+    }
+}
+
 /// Client -> Server
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ToServer {
+    Handshake(Handshake), // must stay the first variant, so every version can decode it
     Heartbeat,
     Chat(ChatMessage),
     Draw(Draw),
+    ChooseWord(String), // word to draw from the offered choices
     RequestRoom(Option<String>, RoomRequest), // optional nick & request
+    Leader(LeaderCommand), // only accepted from the room leader
     LeaveRoom,
     Disconnect,
+    Profile(ProfileKey), // persistent profile to count this player's games towards
+    Leaderboard(LeaderboardPeriod),
 }
 
 /// Server -> Client
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum ToClient {
+    // `Handshake` and `Disconnect` must keep their position, so every version can decode them
+    Handshake(Handshake),
+    Disconnect(String),
     RoomEvent(RoomEvent),
     JoinRoom(InitialRoomState),
-    LeaveRoom(Option<String>), // reason for leaving
-    Disconnect(String),
+    LeaveRoom(Option<String>),        // reason for leaving
+    Leaderboard(Option<Leaderboard>), // none if the server doesn't keep profiles
+}
+
+/// Secret handed to a player on joining a room, used to resume their place after a dropped
+/// connection
+pub type ResumeToken = u64;
+
+/// Secret a client keeps between connections, identifies its profile on a server
+pub type ProfileKey = u128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LeaderboardPeriod {
+    AllTime,
+    Weekly, // the last seven days
+}
+
+/// what a profile achieved, over all its games or a period of time
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileStats {
+    pub games_played: u64,
+    pub wins: u64,
+    pub total_score: u64,
+    pub words_guessed: u64,
+}
+
+impl ProfileStats {
+    pub fn add(&mut self, other: &ProfileStats) {
+        self.games_played += other.games_played;
+        self.wins += other.wins;
+        self.total_score += other.total_score;
+        self.words_guessed += other.words_guessed;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub period: LeaderboardPeriod,
+    pub entries: Vec<(String, ProfileStats)>, // profile name & stats, best first
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum RoomRequest {
     Find,
     Create,
-    Join(String), // room-key
+    Join(String),     // room-key
+    Spectate(String), // room-key
+    Resume { key: String, token: ResumeToken },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum LeaderCommand {
+    StartGame,
+    SetGameOpts(GameOpts), // only before the game starts
+    Kick(UserId),
+    SetLeader(UserId),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Draw {
     Clear,
     Erase(Coord),
-    Paint { points: Vec<Coord>, color: Color },
+    Paint {
+        points: Vec<Coord>,
+        color: Color,
+        brush: Brush,
+    },
+    Fill {
+        origin: Coord,
+        color: Color,
+    },
+    Shape {
+        shape: Shape,
+        color: Color,
+        brush: Brush,
+    },
+    EndStroke, // mouse released, ends a freehand stroke of paints and erases
+    Undo,
+    Redo,
+}
+
+impl Draw {
+    /// whether every point is on a canvas of the given dimensions and the brush isn't oversized
+    pub fn is_valid(&self, (width, height): Coord) -> bool {
+        let on_canvas = |(x, y): &Coord| *x < width && *y < height;
+
+        match self {
+            Draw::Clear | Draw::EndStroke | Draw::Undo | Draw::Redo => true,
+            Draw::Paint { points, brush, .. } => {
+                brush.radius <= Brush::MAX_RADIUS && points.iter().all(on_canvas)
+            }
+            Draw::Shape { shape, brush, .. } => {
+                brush.radius <= Brush::MAX_RADIUS && on_canvas(&shape.from) && on_canvas(&shape.to)
+            }
+            Draw::Erase(point) | Draw::Fill { origin: point, .. } => on_canvas(point),
+        }
+    }
+
+    /// append the points of `next`, if both paint with the same color and brush
+    pub fn coalesce(&mut self, next: &Draw) -> bool {
+        match (self, next) {
+            (
+                Draw::Paint {
+                    points,
+                    color,
+                    brush,
+                },
+                Draw::Paint {
+                    points: next_points,
+                    color: next_color,
+                    brush: next_brush,
+                },
+            ) if color == next_color && brush == next_brush => {
+                points.extend(next_points);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// apply this to a map of coord-color pairs, brush strokes are clipped to the dimensions
+    pub fn apply(&self, canvas: &mut HashMap<Coord, Color>, dimensions: Coord) {
+        match self {
+            Draw::Clear => canvas.clear(),
+            Draw::Paint {
+                points,
+                color,
+                brush,
+            } => {
+                for point in points {
+                    for pixel in brush.stamp(*point, dimensions) {
+                        canvas.insert(pixel, *color);
+                    }
+                }
+            }
+            Draw::Erase(point) => {
+                canvas.remove(point);
+            }
+            Draw::Fill { origin, color } => flood_fill(canvas, *origin, *color, dimensions),
+            Draw::Shape {
+                shape,
+                color,
+                brush,
+            } => {
+                // the brush only thickens the outline, the inside of a filled shape is plain
+                if shape.filled {
+                    for pixel in shape.area() {
+                        canvas.insert(pixel, *color);
+                    }
+                }
+
+                for point in shape.outline() {
+                    for pixel in brush.stamp(point, dimensions) {
+                        canvas.insert(pixel, *color);
+                    }
+                }
+            }
+            // resolved by the server from its stroke history, which sends a `CanvasPatch`
+            Draw::EndStroke | Draw::Undo | Draw::Redo => {}
+        };
+    }
+}
+
+/// Pixels that changed between two versions of a canvas
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CanvasPatch {
+    pub painted: Vec<(Coord, Color)>,
+    pub erased: Vec<Coord>,
+}
+
+impl CanvasPatch {
+    pub fn between(old: &HashMap<Coord, Color>, new: &HashMap<Coord, Color>) -> Self {
+        Self {
+            painted: new
+                .iter()
+                .filter(|(point, color)| old.get(point) != Some(color))
+                .map(|(point, color)| (*point, *color))
+                .collect(),
+            erased: old
+                .keys()
+                .filter(|point| !new.contains_key(point))
+                .copied()
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.painted.is_empty() && self.erased.is_empty() }
+
+    pub fn apply(&self, canvas: &mut HashMap<Coord, Color>) {
+        for point in &self.erased {
+            canvas.remove(point);
+        }
+        canvas.extend(self.painted.iter().copied());
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShapeKind {
+    Line,
+    Rect,
+    Ellipse,
+}
+
+/// Shape spanned by dragging from one point to another, rects and ellipses fill the box between
+/// both points
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shape {
+    pub kind: ShapeKind,
+    pub from: Coord,
+    pub to: Coord,
+    pub filled: bool, // ignored for lines
+}
+
+impl Shape {
+    /// pixels on the outline of this shape
+    pub fn outline(&self) -> Vec<Coord> {
+        match self.kind {
+            ShapeKind::Line => line_points(self.from, self.to),
+            ShapeKind::Rect => {
+                let ((x0, y0), (x1, y1)) = self.bounds();
+                let mut points = line_points((x0, y0), (x1, y0));
+                points.extend(line_points((x0, y1), (x1, y1)));
+                points.extend(line_points((x0, y0), (x0, y1)));
+                points.extend(line_points((x1, y0), (x1, y1)));
+                points
+            }
+            ShapeKind::Ellipse => {
+                let area = self.area();
+                let inside: HashSet<Coord> = area.iter().copied().collect();
+                let is_edge = |(x, y): Coord| {
+                    let neighbours = [
+                        (x.wrapping_sub(1), y),
+                        (x + 1, y),
+                        (x, y.wrapping_sub(1)),
+                        (x, y + 1),
+                    ];
+                    neighbours.iter().any(|point| !inside.contains(point))
+                };
+
+                area.iter()
+                    .copied()
+                    .filter(|point| is_edge(*point))
+                    .collect()
+            }
+        }
+    }
+
+    /// pixels covered by this shape including its inside
+    pub fn area(&self) -> Vec<Coord> {
+        let ((x0, y0), (x1, y1)) = self.bounds();
+        let points = (y0..=y1).flat_map(|y| (x0..=x1).map(move |x| (x, y)));
+
+        match self.kind {
+            ShapeKind::Line => self.outline(),
+            ShapeKind::Rect => points.collect(),
+            ShapeKind::Ellipse => {
+                // in doubled coordinates, so the center of even sized boxes stays an integer
+                let (width, height) = ((x1 - x0 + 1) as i64, (y1 - y0 + 1) as i64);
+                let (cx, cy) = ((x0 + x1) as i64, (y0 + y1) as i64);
+
+                points
+                    .filter(|(x, y)| {
+                        let dx = 2 * *x as i64 - cx;
+                        let dy = 2 * *y as i64 - cy;
+                        dx * dx * height * height + dy * dy * width * width
+                            <= width * width * height * height
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// top left and bottom right corner of the box spanned by this shape
+    fn bounds(&self) -> (Coord, Coord) {
+        let (x0, x1) = (self.from.0.min(self.to.0), self.from.0.max(self.to.0));
+        let (y0, y1) = (self.from.1.min(self.to.1), self.from.1.max(self.to.1));
+        ((x0, y0), (x1, y1))
+    }
+}
+
+/// all points on the line between two points, both included
+pub fn line_points(from: Coord, to: Coord) -> Vec<Coord> {
+    let (from, to) = ((from.0 as i32, from.1 as i32), (to.0 as i32, to.1 as i32));
+
+    Bresenham::new(from, to)
+        .map(|(x, y)| (x as u16, y as u16))
+        .collect()
+}
+
+/// paint the area of same-colored pixels connected to `origin`, without crossing diagonals or
+/// the canvas edges
+fn flood_fill(
+    canvas: &mut HashMap<Coord, Color>,
+    origin: Coord,
+    color: Color,
+    (width, height): Coord,
+) {
+    let target = canvas.get(&origin).copied();
+    if origin.0 >= width || origin.1 >= height || target == Some(color) {
+        return;
+    }
+
+    let mut stack = vec![origin];
+    while let Some((x, y)) = stack.pop() {
+        if canvas.get(&(x, y)).copied() != target {
+            continue;
+        }
+        canvas.insert((x, y), color);
+
+        if x > 0 {
+            stack.push((x - 1, y));
+        }
+        if x + 1 < width {
+            stack.push((x + 1, y));
+        }
+        if y > 0 {
+            stack.push((x, y - 1));
+        }
+        if y + 1 < height {
+            stack.push((x, y + 1));
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BrushTip {
+    #[default]
+    Round,
+    Square,
+}
+
+/// Brush used for each point of a `Draw::Paint`, a radius of `0` paints a single pixel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Brush {
+    pub radius: u8,
+    pub tip: BrushTip,
+}
+
+impl Brush {
+    pub const MAX_RADIUS: u8 = 8;
+
+    /// canvas pixels covered by putting the brush down on `center`
+    pub fn stamp(&self, center: Coord, (width, height): Coord) -> Vec<Coord> {
+        let radius = self.radius as i32;
+        let (cx, cy) = (center.0 as i32, center.1 as i32);
+
+        let mut pixels = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if self.tip == BrushTip::Round && dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+
+                let (x, y) = (cx + dx, cy + dy);
+                if x >= 0 && y >= 0 && x < width as i32 && y < height as i32 {
+                    pixels.push((x as u16, y as u16));
+                }
+            }
+        }
+
+        pixels
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,16 +464,18 @@ pub enum RoomEvent {
     EndGame, // return players to room lobby
     UserJoin(Username),
     UserLeave(Username),
+    RoomUpdate(RoomInfo), // room options or leader changed
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GameEvent {
     PlayerJoin(PlayerData),
-    PlayGuessed(Username),
+    PlayerGuessed(Username),
     PlayerListUpdate(Vec<PlayerData>),
     StateUpdate(GameState),
     WordHint((usize, char)), // index and char to reveal
     Draw(Draw),
+    CanvasPatch(CanvasPatch), // canvas changed by an undo or redo
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -66,15 +483,19 @@ pub struct InitialRoomState {
     pub username: Username,
     pub room: RoomInfo,
     pub game: Option<GameInfo>,
+    pub is_spectator: bool,
+    pub resume_token: Option<ResumeToken>, // spectators can't resume
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RoomInfo {
     pub key: String,
     pub connected_users: Vec<Username>,
+    pub spectators: Vec<Username>,
     pub game_opts: GameOpts,
     pub leader: Option<Username>,
-    // pub max_room_size: usize,
+    pub word_packs: Vec<WordPackInfo>, // packs available on the server
+                                       // pub max_room_size: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -95,8 +516,8 @@ impl ChatMessage {
 
     pub fn inner(&self) -> &str {
         match self {
-            ChatMessage::System(msg) => &msg,
-            ChatMessage::User(_, msg) => &msg,
+            ChatMessage::System(msg) => msg,
+            ChatMessage::User(_, msg) => msg,
         }
     }
 
@@ -115,3 +536,96 @@ impl fmt::Display for ChatMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paint(points: Vec<Coord>, color: Color, radius: u8) -> Draw {
+        Draw::Paint {
+            points,
+            color,
+            brush: Brush {
+                radius,
+                ..Brush::default()
+            },
+        }
+    }
+
+    #[test]
+    fn paints_with_the_same_color_and_brush_coalesce() {
+        let mut draw = paint(vec![(0, 0), (1, 0)], Color::Red, 1);
+
+        assert!(draw.coalesce(&paint(vec![(2, 0)], Color::Red, 1)));
+        match draw {
+            Draw::Paint { points, .. } => assert_eq!(points, vec![(0, 0), (1, 0), (2, 0)]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn paints_with_another_color_or_brush_stay_apart() {
+        let mut draw = paint(vec![(0, 0)], Color::Red, 1);
+
+        assert!(!draw.coalesce(&paint(vec![(1, 0)], Color::Blue, 1)));
+        assert!(!draw.coalesce(&paint(vec![(1, 0)], Color::Red, 2)));
+        assert!(!draw.coalesce(&Draw::EndStroke));
+        assert!(!Draw::EndStroke.coalesce(&paint(vec![(1, 0)], Color::Red, 1)));
+        match draw {
+            Draw::Paint { points, .. } => assert_eq!(points, vec![(0, 0)]),
+            _ => unreachable!(),
+        }
+    }
+
+    /// the encoding of these messages is part of the wire protocol, if this fails bump
+    /// `PROTOCOL_VERSION` along with the expected bytes
+    #[test]
+    fn wire_format_matches_the_protocol_version() {
+        let draws = vec![
+            paint(vec![(1, 2)], Color::Red, 3),
+            Draw::Fill {
+                origin: (4, 5),
+                color: Color::Blue,
+            },
+            Draw::Shape {
+                shape: Shape {
+                    kind: ShapeKind::Ellipse,
+                    from: (1, 2),
+                    to: (3, 4),
+                    filled: true,
+                },
+                color: Color::Green,
+                brush: Brush::default(),
+            },
+            Draw::Undo,
+        ];
+        let patch = GameEvent::CanvasPatch(CanvasPatch {
+            painted: vec![((1, 2), Color::Red)],
+            erased: vec![(3, 4)],
+        });
+
+        assert_eq!(PROTOCOL_VERSION, 4);
+        assert_eq!(
+            bincode::serialize(&ToServer::Draw(draws[0].clone())).unwrap(),
+            [
+                3, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2, 0, 4, 0, 0, 0, 3, 0, 0, 0,
+                0
+            ]
+        );
+        assert_eq!(
+            bincode::serialize(&draws).unwrap(),
+            [
+                4, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2, 0, 4, 0, 0, 0,
+                3, 0, 0, 0, 0, 3, 0, 0, 0, 4, 0, 5, 0, 8, 0, 0, 0, 4, 0, 0, 0, 2, 0, 0, 0, 1, 0, 2,
+                0, 3, 0, 4, 0, 1, 6, 0, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0
+            ]
+        );
+        assert_eq!(
+            bincode::serialize(&ToClient::RoomEvent(RoomEvent::GameEvent(patch))).unwrap(),
+            [
+                2, 0, 0, 0, 1, 0, 0, 0, 6, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2, 0, 4, 0, 0, 0,
+                1, 0, 0, 0, 0, 0, 0, 0, 3, 0, 4, 0
+            ]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    iter,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    data::{GameState, TurnPhase},
+    message::{GameEvent, RoomEvent, RoomInfo, PROTOCOL_VERSION},
+    utils,
+};
+
+/// first bytes of every replay file, followed by the protocol version as a big endian u16
+const MAGIC: &[u8] = b"TERMIBBL-REPLAY\n";
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    #[error("could not read replay: {0}")]
+    IOError(#[from] io::Error),
+
+    #[error("not a termibbl replay")]
+    NotAReplay,
+
+    #[error(
+        "replay was recorded with protocol version {0}, expected {}",
+        PROTOCOL_VERSION
+    )]
+    Version(u16),
+
+    #[error("replay is corrupt: {0}")]
+    Serialization(#[from] bincode::Error),
+}
+
+/// the room a replay was recorded in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    /// unix time in seconds the game started at
+    pub started_at: u64,
+    pub room: RoomInfo,
+}
+
+/// an event broadcast by the room and when, in milliseconds since the game started
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    pub millis: u64,
+    pub event: RoomEvent,
+}
+
+impl ReplayEntry {
+    /// round number if this event starts a round
+    pub fn round_start(&self) -> Option<usize> {
+        match self.event {
+            RoomEvent::GameEvent(GameEvent::StateUpdate(GameState::RoundStart(round))) => {
+                Some(round)
+            }
+            _ => None,
+        }
+    }
+
+    /// whether this event starts a turn, when the drawing player gets to choose a word
+    pub fn is_turn_start(&self) -> bool {
+        match &self.event {
+            RoomEvent::GameEvent(GameEvent::StateUpdate(GameState::Playing(turn))) => {
+                matches!(turn.phase, TurnPhase::ChoosingWord(_))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// a recorded game as seen by a spectator
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub entries: Vec<ReplayEntry>,
+}
+
+impl Replay {
+    /// read a replay file, a replay cut short by a crashing server ends at its last whole entry
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let rest = bytes.strip_prefix(MAGIC).ok_or(Error::NotAReplay)?;
+
+        if rest.len() < 2 {
+            return Err(Error::NotAReplay);
+        }
+        let version = u16::from_be_bytes([rest[0], rest[1]]);
+        if version != PROTOCOL_VERSION {
+            return Err(Error::Version(version));
+        }
+
+        let mut frames = &rest[2..];
+        let header = read_frame(&mut frames)?.ok_or(Error::NotAReplay)?;
+
+        let mut entries = Vec::new();
+        while let Some(entry) = read_frame(&mut frames)? {
+            entries.push(entry);
+        }
+
+        Ok(Replay { header, entries })
+    }
+
+    /// milliseconds from the start of the game to its last event
+    pub fn duration(&self) -> u64 { self.entries.last().map_or(0, |entry| entry.millis) }
+}
+
+/// next length prefixed frame, none if there is no whole frame left
+fn read_frame<T: DeserializeOwned>(frames: &mut &[u8]) -> Result<Option<T>> {
+    if frames.len() < 4 {
+        return Ok(None);
+    }
+
+    let len = u32::from_be_bytes([frames[0], frames[1], frames[2], frames[3]]) as usize;
+    if frames.len() < 4 + len {
+        return Ok(None);
+    }
+
+    let value = bincode::deserialize(&frames[4..4 + len])?;
+    *frames = &frames[4 + len..];
+    Ok(Some(value))
+}
+
+fn write_frame<T: Serialize>(writer: &mut impl Write, value: &T) -> Result<()> {
+    let frame = bincode::serialize(value)?;
+    writer.write_all(&(frame.len() as u32).to_be_bytes())?;
+    writer.write_all(&frame)?;
+    Ok(())
+}
+
+/// Writes the events of a game to a replay file as they are broadcast.
+///
+/// Entries are handed to a task that writes them in batches, the file ends when the recorder is
+/// dropped.
+pub struct ReplayRecorder {
+    started: Instant,
+    entries: flume::Sender<ReplayEntry>,
+}
+
+impl ReplayRecorder {
+    /// start a new replay file in `dir` for a game that starts now, named after the room and the
+    /// time with a counter if another game of the room started in the same second
+    pub fn create(dir: &Path, room: RoomInfo) -> Result<(Self, PathBuf)> {
+        let started_at = utils::get_time_now();
+
+        fs::create_dir_all(dir)?;
+        let (file, path) = create_free(dir, &format!("{}-{}", room.key, started_at))?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&PROTOCOL_VERSION.to_be_bytes())?;
+        write_frame(&mut writer, &ReplayHeader { started_at, room })?;
+
+        let (sender, receiver) = flume::unbounded::<ReplayEntry>();
+        let task_path = path.clone();
+        tokio::spawn(async move {
+            if let Err(e) = write_entries(receiver, writer).await {
+                log::error!("stopped recording {}: {}", task_path.display(), e);
+            }
+        });
+
+        let recorder = ReplayRecorder {
+            started: Instant::now(),
+            entries: sender,
+        };
+        Ok((recorder, path))
+    }
+
+    /// add an event to the replay, timed from when the recorder was created
+    pub fn record(&self, event: &RoomEvent) {
+        let _ = self.entries.send(ReplayEntry {
+            millis: self.started.elapsed().as_millis() as u64,
+            event: event.clone(),
+        });
+    }
+}
+
+/// create `<stem>.replay`, or `<stem>-<n>.replay` with the first free counter, never replaces a
+/// file
+fn create_free(dir: &Path, stem: &str) -> io::Result<(File, PathBuf)> {
+    let names = iter::once(format!("{}.replay", stem))
+        .chain((2..).map(|n| format!("{}-{}.replay", stem, n)));
+
+    for path in names.map(|name| dir.join(name)) {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!("endless counter")
+}
+
+/// write entries until the recorder is dropped, a blocking thread is only taken while a batch of
+/// the entries that came in meanwhile is written
+async fn write_entries(
+    receiver: flume::Receiver<ReplayEntry>,
+    mut writer: BufWriter<File>,
+) -> Result<()> {
+    while let Ok(entry) = receiver.recv_async().await {
+        let batch: Vec<_> = iter::once(entry).chain(receiver.try_iter()).collect();
+
+        writer = tokio::task::spawn_blocking(move || -> Result<_> {
+            for entry in &batch {
+                write_frame(&mut writer, entry)?;
+            }
+
+            // write out every batch, a replay should survive a crashing room
+            writer.flush()?;
+            Ok(writer)
+        })
+        .await
+        .map_err(io::Error::other)??;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{GameMode, GameOpts, Scoring},
+        message::ChatMessage,
+    };
+
+    fn room() -> RoomInfo {
+        RoomInfo {
+            key: "abcde".to_owned(),
+            connected_users: Vec::new(),
+            spectators: Vec::new(),
+            game_opts: GameOpts {
+                dimensions: (120, 90),
+                number_of_rounds: 3,
+                draw_time: 80,
+                custom_words: Vec::new(),
+                only_custom_words: false,
+                custom_words_ratio: 66,
+                word_packs: Vec::new(),
+                game_mode: GameMode::FreeForAll,
+                scoring: Scoring::Classic,
+            },
+            leader: None,
+            word_packs: Vec::new(),
+        }
+    }
+
+    fn replay_bytes(version: u16, events: &[RoomEvent]) -> Vec<u8> {
+        let header = ReplayHeader {
+            started_at: 0,
+            room: room(),
+        };
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&version.to_be_bytes());
+        write_frame(&mut bytes, &header).unwrap();
+        for (millis, event) in events.iter().enumerate() {
+            let entry = ReplayEntry {
+                millis: millis as u64 * 100,
+                event: event.clone(),
+            };
+            write_frame(&mut bytes, &entry).unwrap();
+        }
+        bytes
+    }
+
+    fn load(bytes: &[u8]) -> Result<Replay> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        Replay::load(file.path())
+    }
+
+    fn chat(msg: &str) -> RoomEvent { RoomEvent::Chat(ChatMessage::System(msg.to_owned())) }
+
+    #[test]
+    fn reads_entries_up_to_a_cut_off_tail() {
+        let mut bytes = replay_bytes(PROTOCOL_VERSION, &[chat("one"), chat("two")]);
+        bytes.truncate(bytes.len() - 3);
+
+        let replay = load(&bytes).unwrap();
+        assert_eq!(replay.header.room.key, "abcde");
+        assert_eq!(replay.entries.len(), 1);
+        assert_eq!(replay.duration(), 0);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let bytes = replay_bytes(PROTOCOL_VERSION + 1, &[chat("one")]);
+        assert!(matches!(load(&bytes), Err(Error::Version(_))));
+        assert!(matches!(load(b"not a replay"), Err(Error::NotAReplay)));
+    }
+
+    #[tokio::test]
+    async fn games_started_in_the_same_second_get_their_own_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, first_path) = ReplayRecorder::create(dir.path(), room()).unwrap();
+        let (second, second_path) = ReplayRecorder::create(dir.path(), room()).unwrap();
+        assert_ne!(first_path, second_path);
+
+        first.record(&chat("one"));
+        second.record(&chat("two"));
+        second.record(&chat("three"));
+        drop((first, second));
+        tokio::time::delay_for(std::time::Duration::from_millis(200)).await;
+
+        assert_eq!(Replay::load(&first_path).unwrap().entries.len(), 1);
+        assert_eq!(Replay::load(&second_path).unwrap().entries.len(), 2);
+    }
+}
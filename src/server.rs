@@ -1,30 +1,65 @@
 mod cli;
+mod profiles;
 mod room;
+mod scoring;
 mod session;
 mod skribbl;
+mod word_pack;
 
-pub use self::cli::CliOpts;
-use self::room::{GameRoom, RoomInbox, RoomMessage};
+pub use self::profiles::ProfileStore;
+pub use self::{
+    cli::CliOpts,
+    skribbl::{CHOOSE_WORDS_TIME, FINISH_SECS, REVEAL_PHASE_SECS, ROUND_START_SECS},
+};
+use self::{
+    profiles::GameResult,
+    room::{GameRoom, RoomInbox, RoomMessage},
+    word_pack::WordPack,
+};
 
 use crate::{
     data::{GameOpts, UserId, Username},
     events::{EventQueue, EventSender},
-    message::RoomRequest,
-    utils::{self, AbortableTask},
+    export::Gallery,
+    message::{LeaderboardPeriod, ProfileKey, RoomRequest},
+    utils::{self, AbortableTask, Transport},
+    websocket::{self, WebSocket},
+};
+use futures_util::{
+    stream::{self, Stream},
+    StreamExt,
 };
-use futures_util::StreamExt;
 use rand::{prelude::ThreadRng, Rng};
 use session::{User, UserSession};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 use thiserror::Error;
 use tokio::net::{TcpListener, TcpStream};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// websocket clients that haven't finished their handshake after this many seconds are dropped
+const WEBSOCKET_HANDSHAKE_SECS: u64 = 10;
+
+/// key of the public room that is always open
+const DEFAULT_ROOM_KEY: &str = "default";
+
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("IO error")]
     IOError(#[from] std::io::Error),
+    #[error("could not listen on {addr}: {source}")]
+    Bind {
+        addr: SocketAddr,
+        source: std::io::Error,
+    },
     #[error("empty optional")]
     EmptyOptional,
 }
@@ -43,6 +78,39 @@ pub enum Message {
     /// Notify server of room closing
     RoomClosed(String),
 
+    /// Ask server to close a room, the server tells it once nothing else is sent to it
+    CloseRoom(String),
+
+    /// Notify server of a change in a room's fill level or joinable status, after handling the
+    /// given number of joins
+    RoomStatus {
+        key: String,
+        num_of_users: usize,
+        joins_handled: usize,
+        is_joinable: bool,
+    },
+
+    /// Notify server of the profile a client plays as
+    Profile {
+        id: UserId,
+        key: ProfileKey,
+    },
+
+    /// Ask server for the leaderboard on behalf of a client
+    LeaderboardRequest {
+        id: UserId,
+        period: LeaderboardPeriod,
+    },
+
+    /// Notify server of a game that was played to the end
+    GameFinished(Vec<GameResult>),
+
+    /// Notify server of a websocket client that finished its handshake
+    WebSocketOpened {
+        peer_addr: SocketAddr,
+        socket: Box<WebSocket<TcpStream>>,
+    },
+
     CtrlC,
 }
 
@@ -50,46 +118,94 @@ pub enum Message {
 struct Room {
     inbox: RoomInbox,
     thread_handle: AbortableTask<()>,
+    /// whether this room can be found through matchmaking
+    is_public: bool,
+    /// last known number of users in this room
+    num_of_users: usize,
+    /// whether this room is accepting new players
+    is_joinable: bool,
+    /// number of joins sent to this room, players of joins it did not report yet are counted
+    /// on top of its last status
+    joins_sent: usize,
+    /// whether this room was told to close, nothing else is sent to it
+    is_closing: bool,
+}
+
+impl Room {
+    /// whether a queued player could be placed in this room
+    fn can_matchmake(&self) -> bool {
+        self.is_public
+            && self.is_joinable
+            && !self.is_closing
+            && self.num_of_users < room::MAX_ROOM_SIZE
+    }
 }
 
 pub struct GameServer {
     event_queue: EventQueue<Message>,
     /// hold game rooms by thier key
     rooms: HashMap<String, Room>,
-    /// list of words
-    words: Arc<Vec<String>>,
+    /// word packs loaded by the server
+    word_packs: Arc<Vec<WordPack>>,
     /// holds the default game configuration
     default_game_opts: GameOpts,
-    // /// list of players searching for a game
-    // game_queue: Vec<UserId>,
+    /// where rooms save finished drawings, if anywhere
+    gallery: Option<Gallery>,
+    /// directory rooms record replays of their games to, if any
+    replays: Option<PathBuf>,
+    /// persistent player profiles, if the server keeps them
+    profiles: Option<ProfileStore>,
+    /// list of players searching for a game
+    game_queue: VecDeque<Username>,
     /// holds connected users by id
     connected_users: HashMap<UserId, User>,
+    /// new connections are refused once this many users are connected
+    max_users: usize,
+    /// id handed to the next connecting user
+    next_id: UserId,
     /// random number generator for id & name generation
     rng: ThreadRng,
 }
 
 impl GameServer {
-    pub fn new(default_game_opts: GameOpts, default_words: Vec<String>) -> Self {
+    pub fn new(
+        default_game_opts: GameOpts,
+        word_packs: Vec<WordPack>,
+        gallery: Option<Gallery>,
+        replays: Option<PathBuf>,
+        profiles: Option<ProfileStore>,
+        max_users: usize,
+    ) -> Self {
         Self {
             event_queue: EventQueue::default(),
             rooms: HashMap::new(),
-            words: Arc::new(default_words),
+            word_packs: Arc::new(word_packs),
             default_game_opts,
-            // game_queue: Vec::new(),
+            gallery,
+            replays,
+            profiles,
+            game_queue: VecDeque::new(),
             connected_users: HashMap::new(),
+            max_users,
+            next_id: 0,
             rng: rand::thread_rng(),
         }
     }
 
     pub fn sender(&self) -> &EventSender<Message> { self.event_queue.sender() }
 
-    /// generate unique u8
-    fn gen_unique_id(&mut self) -> u8 {
-        // garenteed to return if max num of players is 2^8
+    /// next id not held by a connected user, none if the server is full
+    fn gen_unique_id(&mut self) -> Option<UserId> {
+        if self.connected_users.len() >= self.max_users {
+            return None;
+        }
+
+        // ids only come around again after wrapping, so this ends well before that
         loop {
-            let id: u8 = self.rng.gen();
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
             if !self.connected_users.contains_key(&id) {
-                return id;
+                return Some(id);
             }
         }
     }
@@ -108,6 +224,8 @@ impl GameServer {
     }
 
     fn on_client_disconnect(&mut self, id: UserId) {
+        self.game_queue.retain(|name| name.id() != id);
+
         if self.connected_users.remove(&id).is_some() {
             log::info!("#{} left the server", id);
         }
@@ -120,17 +238,101 @@ impl GameServer {
         }
     }
 
+    /// a room that can be sent requests, rooms that are closing are gone already
+    fn open_room(&self, key: &str) -> Option<&Room> {
+        self.rooms.get(key).filter(|room| !room.is_closing)
+    }
+
+    /// tell a room to close, after anything sent to it before so every request gets an answer
+    fn close_room(&mut self, key: &str) {
+        if let Some(room) = self.rooms.get_mut(key).filter(|room| !room.is_closing) {
+            room.is_closing = true;
+            room.inbox.send(RoomMessage::Close);
+        }
+    }
+
     fn on_room_close(&mut self, key: String) {
-        if key.as_str() == "default" {
-            log::info!("did not close main room");
-        } else if let Some(_room) = self.rooms.remove(&key) {
+        if self.rooms.remove(&key).is_some() {
             log::info!("closed room {}", key)
         }
+
+        if key == DEFAULT_ROOM_KEY {
+            log::info!("reopening main room");
+            self.dispatch_room(key, None);
+        }
+    }
+
+    fn on_room_status(
+        &mut self,
+        key: String,
+        num_of_users: usize,
+        joins_handled: usize,
+        is_joinable: bool,
+    ) {
+        if let Some(room) = self.rooms.get_mut(&key) {
+            // joins sent after this status are still on their way
+            room.num_of_users = num_of_users + room.joins_sent.saturating_sub(joins_handled);
+            room.is_joinable = is_joinable;
+
+            // nobody is in or on their way to an empty public room, the main room stays open
+            if room.is_public && room.num_of_users == 0 && key != DEFAULT_ROOM_KEY {
+                self.close_room(&key);
+            }
+        }
+    }
+
+    fn on_profile(&mut self, id: UserId, key: ProfileKey) {
+        if let Some(user) = self.connected_users.get_mut(&id) {
+            user.profile = Some(key);
+        }
+    }
+
+    /// read the leaderboard off the server loop, it goes through every profile or game
+    fn on_leaderboard_request(&self, id: UserId, period: LeaderboardPeriod) {
+        let inbox = match self.connected_users.get(&id) {
+            Some(user) => user.inbox.clone(),
+            None => return,
+        };
+        let store = match &self.profiles {
+            Some(store) => store.clone(),
+            None => return inbox.send(session::Message::Leaderboard(None)),
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let leaderboard = store
+                .leaderboard(period)
+                .map_err(|e| log::error!("could not read profiles: {}", e))
+                .ok();
+            inbox.send(session::Message::Leaderboard(leaderboard));
+        });
+    }
+
+    /// count a finished game towards the profiles of players that have one, off the server loop
+    fn on_game_finished(&self, results: Vec<GameResult>) {
+        let store = match &self.profiles {
+            Some(store) => store.clone(),
+            None => return,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = store.record_game(&results) {
+                log::error!("could not save profiles: {}", e);
+            }
+        });
     }
 
     fn dispatch_room(&mut self, key: String, leader: Option<Username>) {
-        let mut room = GameRoom::new(key, self.default_game_opts.clone(), &self.words, leader);
         let server = self.sender().clone();
+        let is_public = leader.is_none();
+        let mut room = GameRoom::new(
+            key,
+            self.default_game_opts.clone(),
+            &self.word_packs,
+            leader,
+            server.clone(),
+            self.gallery.clone(),
+            self.replays.clone(),
+        );
         let sender = room.sender().clone();
         let room_key = room.key().to_owned();
 
@@ -150,46 +352,120 @@ impl GameServer {
             Room {
                 inbox: sender,
                 thread_handle,
+                is_public,
+                num_of_users: 0,
+                is_joinable: true,
+                joins_sent: 0,
+                is_closing: false,
             },
         );
     }
 
+    /// place every player in the game queue in the least-full public room that
+    /// has not started yet, dispatching a new public room when none fits
+    fn process_game_queue(&mut self) {
+        while let Some(name) = self.game_queue.pop_front() {
+            let (inbox, profile) = if let Some(user) = self.connected_users.get(&name.id()) {
+                (user.inbox.clone(), user.profile)
+            } else {
+                continue;
+            };
+
+            let room_key = self
+                .rooms
+                .iter()
+                .filter(|(_, room)| room.can_matchmake())
+                .min_by_key(|(_, room)| room.num_of_users)
+                .map(|(key, _)| key.clone())
+                .unwrap_or_else(|| {
+                    let room_key = self.gen_key();
+                    self.dispatch_room(room_key.clone(), None);
+
+                    room_key
+                });
+
+            log::debug!("matched {:?} with room {}", name, room_key);
+
+            let room = self.rooms.get_mut(&room_key).unwrap();
+            // count this player now, the room reports its actual status once joined
+            room.num_of_users += 1;
+            room.joins_sent += 1;
+            room.inbox.send(RoomMessage::Join {
+                name,
+                inbox,
+                profile,
+            });
+        }
+    }
+
     fn on_room_request(&mut self, name: Username, action: RoomRequest) {
         let user_id = name.id();
-        let inbox = if let Some(user) = self.connected_users.get_mut(&user_id) {
-            user.inbox.clone()
+        let (inbox, profile) = if let Some(user) = self.connected_users.get(&user_id) {
+            (user.inbox.clone(), user.profile)
         } else {
             return;
         };
 
         let room_key = match action {
             RoomRequest::Join(room_key) => room_key,
+            RoomRequest::Spectate(key) => {
+                return match self.open_room(&key) {
+                    Some(room) => room.inbox.send(RoomMessage::Spectate { name, inbox }),
+                    None => inbox.send_with_urgency(session::Message::RoomNotFound),
+                };
+            }
+            RoomRequest::Resume { key, token } => {
+                return match self.open_room(&key) {
+                    Some(room) => room.inbox.send(RoomMessage::Resume {
+                        token,
+                        session: user_id,
+                        inbox,
+                    }),
+                    None => inbox.send_with_urgency(session::Message::RoomNotFound),
+                };
+            }
             RoomRequest::Create => {
                 let room_key = self.gen_key();
                 self.dispatch_room(room_key.clone(), Some(name.clone()));
 
                 room_key
             }
-            _ => {
-                // TODO: allow users to queue for game rooms
-                return self.kick_user(name.id(), "Unimplemented feature".to_owned());
+            RoomRequest::Find => {
+                self.game_queue.push_back(name);
+                return self.process_game_queue();
             }
         };
 
-        if let Some(room) = self.rooms.get(&room_key) {
-            room.inbox.send(RoomMessage::Join { name, inbox });
+        if let Some(room) = self
+            .rooms
+            .get_mut(&room_key)
+            .filter(|room| !room.is_closing)
+        {
+            room.joins_sent += 1;
+            room.inbox.send(RoomMessage::Join {
+                name,
+                inbox,
+                profile,
+            });
         } else {
             inbox.send_with_urgency(session::Message::RoomNotFound);
         }
     }
 
     /// handle stream of TcpStream's
-    fn on_client_connect(&mut self, peer_addr: SocketAddr, st: TcpStream) {
+    fn on_client_connect<S: Transport>(&mut self, peer_addr: SocketAddr, st: S) {
         log::info!("new client connection: {}", peer_addr);
 
-        let unique_id = self.gen_unique_id();
-        let sender = self.event_queue.sender().clone();
         let framed_socket_io = utils::frame_socket(st);
+        let unique_id = match self.gen_unique_id() {
+            Some(id) => id,
+            None => {
+                log::warn!("refusing {}, server is full", peer_addr);
+                tokio::spawn(session::refuse(framed_socket_io.1, "Server is full"));
+                return;
+            }
+        };
+        let sender = self.event_queue.sender().clone();
 
         self.connected_users.insert(
             unique_id,
@@ -197,27 +473,65 @@ impl GameServer {
         );
     }
 
-    /// start server listener on given address
-    pub async fn listen_on(mut self, addr: &str) -> Result<()> {
-        // start tcp listener :: TODO: maybe use udp or both instead?
-        let mut tcp_listener = TcpListener::bind(addr)
-            .await
-            .expect("Could not start webserver (could not bind)")
-            .map(|stream| {
-                let st = stream.unwrap();
-                let addr = st.peer_addr().unwrap();
+    /// finish the handshake of a websocket client away from the accept loop, its session is
+    /// created once the handshake is done
+    fn on_websocket_connect(&self, peer_addr: SocketAddr, st: TcpStream) {
+        log::debug!("new websocket connection: {}", peer_addr);
+
+        let server = self.event_queue.sender().clone();
+        tokio::spawn(async move {
+            let timeout = Duration::from_secs(WEBSOCKET_HANDSHAKE_SECS);
+
+            match tokio::time::timeout(timeout, websocket::accept(st)).await {
+                Ok(Ok(socket)) => server.send(Message::WebSocketOpened {
+                    peer_addr,
+                    socket: Box::new(socket),
+                }),
+                Ok(Err(e)) => log::info!("({}): websocket handshake failed <> {}", peer_addr, e),
+                Err(_) => log::info!("({}): websocket handshake timed out", peer_addr),
+            }
+        });
+    }
+
+    /// bind a tcp listener, yielding accepted connections with their peer address
+    async fn bind(addr: SocketAddr) -> Result<impl Stream<Item = (TcpStream, SocketAddr)>> {
+        let listener = std_listener(addr)
+            .and_then(TcpListener::from_std)
+            .map_err(|source| Error::Bind { addr, source })?;
+        log::info!("listening on {}", listener.local_addr()?);
+
+        Ok(listener.filter_map(|stream| async move {
+            let accepted = stream.and_then(|st| {
+                let addr = st.peer_addr()?;
+                st.set_nodelay(true)?;
+                st.set_keepalive(Some(Duration::from_secs(1)))?;
+                Ok((st, addr))
+            });
+
+            accepted
+                .map_err(|e| log::warn!("could not accept connection: {}", e))
+                .ok()
+        }))
+    }
 
-                st.set_nodelay(true)
-                    .expect("Failed to set stream as nonblocking");
+    /// bind a listener on every address, yielding the connections accepted by any of them
+    async fn bind_all(addrs: &[SocketAddr]) -> Result<impl Stream<Item = (TcpStream, SocketAddr)>> {
+        let mut listeners = Vec::new();
+        for addr in addrs {
+            listeners.push(Box::pin(Self::bind(*addr).await?));
+        }
 
-                st.set_keepalive(Some(Duration::from_secs(1)))
-                    .expect("Failed to set keepalive");
+        Ok(stream::select_all(listeners))
+    }
 
-                (st, addr)
-            });
+    /// start server listeners on the given addresses, websocket clients connect on `ws_addrs`
+    pub async fn listen_on(mut self, addrs: &[SocketAddr], ws_addrs: &[SocketAddr]) -> Result<()> {
+        // start tcp listeners :: TODO: maybe use udp or both instead?
+        let mut tcp_listener = Self::bind_all(addrs).await?;
+        let mut ws_listener = Self::bind_all(ws_addrs).await?;
 
         // create default game room for NOW
-        self.dispatch_room("default".to_owned(), None);
+        self.dispatch_room(DEFAULT_ROOM_KEY.to_owned(), None);
 
         loop {
             tokio::select! {
@@ -227,11 +541,24 @@ impl GameServer {
                         Message::RoomRequest { from, req, } => self.on_room_request(from, req),
                         Message::Disconnect (id) => self.on_client_disconnect(id),
                         Message::RoomClosed (key)=> self.on_room_close(key),
+                        Message::CloseRoom(key) => self.close_room(&key),
+                        Message::RoomStatus { key, num_of_users, joins_handled, is_joinable } => {
+                            self.on_room_status(key, num_of_users, joins_handled, is_joinable)
+                        }
+                        Message::Profile { id, key } => self.on_profile(id, key),
+                        Message::LeaderboardRequest { id, period } => {
+                            self.on_leaderboard_request(id, period)
+                        }
+                        Message::GameFinished(results) => self.on_game_finished(results),
+                        Message::WebSocketOpened { peer_addr, socket } => {
+                            self.on_client_connect(peer_addr, *socket)
+                        }
                     }
                 }
 
                 // listen and accept incoming connections in async thread.
                 Some((socket, addr)) = tcp_listener.next() => self.on_client_connect(addr, socket),
+                Some((socket, addr)) = ws_listener.next() => self.on_websocket_connect(addr, socket),
 
                 // tcp pipe probably closed, stop server
                 else => break,
@@ -255,3 +582,80 @@ impl GameServer {
         Ok(())
     }
 }
+
+/// a nonblocking listener, IPv6 ones only accept IPv6 so the same port can be bound for IPv4 too
+fn std_listener(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    let domain = if addr.is_ipv6() {
+        Domain::ipv6()
+    } else {
+        Domain::ipv4()
+    };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?; // as tokio does, so a restarted server can bind right away
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+
+    Ok(socket.into_tcp_listener())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{GameMode, Scoring};
+
+    fn server() -> GameServer {
+        let opts = GameOpts {
+            dimensions: cli::DEFAULT_DIMENSIONS,
+            number_of_rounds: 3,
+            draw_time: 80,
+            custom_words: Vec::new(),
+            only_custom_words: false,
+            custom_words_ratio: 0,
+            word_packs: Vec::new(),
+            game_mode: GameMode::FreeForAll,
+            scoring: Scoring::Classic,
+        };
+
+        GameServer::new(opts, Vec::new(), None, None, None, 10)
+    }
+
+    #[tokio::test]
+    async fn joins_on_their_way_are_counted_over_older_statuses() {
+        let mut server = server();
+        server.dispatch_room("abcde".to_owned(), None);
+        let room = server.rooms.get_mut("abcde").unwrap();
+        room.num_of_users = 2;
+        room.joins_sent = 2;
+
+        // the room had only seen the first join
+        server.on_room_status("abcde".to_owned(), 1, 1, true);
+        assert_eq!(server.rooms["abcde"].num_of_users, 2);
+        assert!(!server.rooms["abcde"].is_closing);
+
+        // both players left again
+        server.on_room_status("abcde".to_owned(), 0, 2, true);
+        assert!(server.rooms["abcde"].is_closing);
+        assert!(server.open_room("abcde").is_none());
+    }
+
+    #[tokio::test]
+    async fn the_main_room_is_kept_open() {
+        let mut server = server();
+        server.dispatch_room(DEFAULT_ROOM_KEY.to_owned(), None);
+
+        server.on_room_status(DEFAULT_ROOM_KEY.to_owned(), 0, 0, true);
+        assert!(server.open_room(DEFAULT_ROOM_KEY).is_some());
+
+        server.close_room(DEFAULT_ROOM_KEY);
+        assert!(server.open_room(DEFAULT_ROOM_KEY).is_none());
+
+        server.on_room_close(DEFAULT_ROOM_KEY.to_owned());
+        assert!(server.open_room(DEFAULT_ROOM_KEY).is_some());
+    }
+}
@@ -1,13 +1,29 @@
 use argh::FromArgs;
-use std::{fs, io::Read};
+use std::{
+    fs,
+    io::Read,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
 
-use super::skribbl;
-use crate::data::{Coord, GameOpts};
+use super::{
+    skribbl,
+    word_pack::{self, WordPack},
+};
+use crate::{
+    data::{Coord, GameMode, GameOpts, Scoring},
+    export::{ExportFormat, Gallery},
+};
 
 pub const DEFAULT_PORT: u16 = 9001;
-pub const DEFAULT_DIMENSIONS: Coord = (100, 900);
+pub const DEFAULT_BIND_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+pub const DEFAULT_DIMENSIONS: Coord = (120, 90);
+/// largest canvas a game can be played on, filling or encoding a canvas scales with its size
+pub const MAX_DIMENSIONS: Coord = (DEFAULT_DIMENSIONS.0 * 4, DEFAULT_DIMENSIONS.1 * 4);
 pub const DEFAULT_WORDS: &str = include_str!("words_dump");
 pub const ROOM_KEY_LENGTH: usize = 5;
+pub const DEFAULT_CUSTOM_WORDS_RATIO: u8 = 66;
+pub const DEFAULT_MAX_USERS: usize = 1000;
 
 type ParseResult<T> = std::result::Result<T, String>;
 
@@ -21,6 +37,58 @@ fn parse_dimension(s: &str) -> ParseResult<Coord> {
         .next()
         .and_then(|width| split.next().map(|height| (width, height)))
         .ok_or_else(|| "could not parse dimensions".to_owned())
+        .and_then(|dimensions| {
+            if valid_dimensions(dimensions) {
+                Ok(dimensions)
+            } else {
+                Err(format!(
+                    "dimensions must be between 1x1 and {}x{}",
+                    MAX_DIMENSIONS.0, MAX_DIMENSIONS.1
+                ))
+            }
+        })
+}
+
+/// whether a game can be played on a canvas of this size
+pub fn valid_dimensions((width, height): Coord) -> bool {
+    (1..=MAX_DIMENSIONS.0).contains(&width) && (1..=MAX_DIMENSIONS.1).contains(&height)
+}
+
+fn parse_percentage(s: &str) -> ParseResult<u8> {
+    s.parse()
+        .ok()
+        .filter(|percent| *percent <= 100)
+        .ok_or_else(|| "expected a percentage between 0 and 100".to_owned())
+}
+
+fn parse_scoring(s: &str) -> ParseResult<Scoring> {
+    match s {
+        "classic" => Ok(Scoring::Classic),
+        "linear" => Ok(Scoring::Linear),
+        "flat" => Ok(Scoring::Flat),
+        _ => Err("expected one of classic, linear or flat".to_owned()),
+    }
+}
+
+/// an ip with an optional port, ipv6 addresses may be put in brackets
+fn parse_bind_addr(s: &str) -> ParseResult<BindAddr> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(BindAddr {
+            ip: addr.ip(),
+            port: Some(addr.port()),
+        });
+    }
+
+    s.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map(|ip| BindAddr { ip, port: None })
+        .map_err(|_| {
+            format!(
+                "expected an ip address like 0.0.0.0 or [::]:{}",
+                DEFAULT_PORT
+            )
+        })
 }
 
 fn parse_words_file(path: &str) -> ParseResult<String> {
@@ -34,6 +102,14 @@ fn parse_words_file(path: &str) -> ParseResult<String> {
     Ok(words)
 }
 
+fn parse_word_packs_dir(path: &str) -> ParseResult<Vec<WordPack>> { word_pack::load_dir(path) }
+
+/// address given to `--bind`, the port defaults to `--port`
+pub struct BindAddr {
+    ip: IpAddr,
+    port: Option<u16>,
+}
+
 /// host a Termibbl session
 #[derive(FromArgs)]
 #[argh(subcommand, name = "server")]
@@ -42,10 +118,23 @@ pub struct CliOpts {
     #[argh(option, short = 'p', default = "DEFAULT_PORT")]
     pub port: u16,
 
+    /// address to listen on, e.g. 0.0.0.0 or [::], can be given more than once, defaults to
+    /// 127.0.0.1
+    #[argh(option, from_str_fn(parse_bind_addr))]
+    bind: Vec<BindAddr>,
+
+    /// optional port to also accept websocket connections on, on every bind address
+    #[argh(option)]
+    websocket_port: Option<u16>,
+
     /// whether to show public ip when server starts
     #[argh(switch, short = 'y')]
     pub display_public_ip: bool,
 
+    /// number of users that can be connected at once, further connections are refused
+    #[argh(option, default = "DEFAULT_MAX_USERS")]
+    pub max_users: usize,
+
     #[argh(option, default = "skribbl::DEFAULT_DRAW_TIME")]
     /// default drawing duration in seconds
     draw_time: u64,
@@ -54,30 +143,121 @@ pub struct CliOpts {
     /// default number of rounds per game
     rounds: usize,
 
-    /// default canvas dimensions <width>x<height>
+    /// default canvas dimensions in pixels <width>x<height>, two pixels fit in a cell vertically
     #[argh(option, default = "DEFAULT_DIMENSIONS", from_str_fn(parse_dimension))]
     dimensions: Coord,
 
     /// optional path to custom word list
     #[argh(option, short = 'w', from_str_fn(parse_words_file))]
     words: Option<String>,
+
+    /// default number of players per team, games are free-for-all if not set
+    #[argh(option)]
+    team_size: Option<usize>,
+
+    /// how guesses are scored: classic, linear or flat
+    #[argh(option, from_str_fn(parse_scoring), default = "Scoring::Classic")]
+    scoring: Scoring,
+
+    /// optional path to a directory of toml word packs
+    #[argh(option, from_str_fn(parse_word_packs_dir))]
+    word_packs: Option<Vec<WordPack>>,
+
+    /// default chance in percent of offering a room's custom word over a server word
+    #[argh(
+        option,
+        default = "DEFAULT_CUSTOM_WORDS_RATIO",
+        from_str_fn(parse_percentage)
+    )]
+    custom_words_ratio: u8,
+
+    /// optional directory every finished drawing is saved to
+    #[argh(option)]
+    gallery: Option<PathBuf>,
+
+    /// format of drawings saved to the gallery: png, svg or ansi
+    #[argh(option, default = "ExportFormat::Png")]
+    gallery_format: ExportFormat,
+
+    /// optional directory a replay of every game is recorded to
+    #[argh(option)]
+    pub replays: Option<PathBuf>,
+
+    /// optional directory player profiles and the leaderboard are kept in
+    #[argh(option)]
+    pub profiles: Option<PathBuf>,
+}
+
+impl CliOpts {
+    /// take the word packs the server was started with, the plain word list is
+    /// included as the `default` pack
+    pub fn take_word_packs(&mut self) -> Vec<WordPack> {
+        let words = self
+            .words
+            .take()
+            .unwrap_or_else(|| DEFAULT_WORDS.to_string());
+
+        let mut packs = vec![WordPack::from_word_list(
+            word_pack::DEFAULT_PACK_NAME,
+            &words,
+        )];
+        packs.extend(self.word_packs.take().unwrap_or_default());
+
+        packs
+    }
+
+    /// addresses the server listens on
+    pub fn bind_addrs(&self) -> Vec<SocketAddr> {
+        if self.bind.is_empty() {
+            return vec![SocketAddr::new(DEFAULT_BIND_IP, self.port)];
+        }
+
+        self.bind
+            .iter()
+            .map(|addr| SocketAddr::new(addr.ip, addr.port.unwrap_or(self.port)))
+            .collect()
+    }
+
+    /// addresses websocket clients connect on, none unless a websocket port is given
+    pub fn websocket_addrs(&self) -> Vec<SocketAddr> {
+        let port = match self.websocket_port {
+            Some(port) => port,
+            None => return Vec::new(),
+        };
+
+        let mut addrs: Vec<SocketAddr> = self
+            .bind_addrs()
+            .into_iter()
+            .map(|addr| SocketAddr::new(addr.ip(), port))
+            .collect();
+        addrs.dedup();
+        addrs
+    }
+
+    /// where finished drawings are saved, if anywhere
+    pub fn gallery(&self) -> Option<Gallery> {
+        self.gallery.clone().map(|dir| Gallery {
+            dir,
+            format: self.gallery_format,
+        })
+    }
 }
 
 impl From<CliOpts> for GameOpts {
-    fn from(mut opt: CliOpts) -> Self {
+    fn from(opt: CliOpts) -> Self {
         GameOpts {
             dimensions: opt.dimensions,
             number_of_rounds: opt.rounds,
             draw_time: opt.draw_time as usize,
-            custom_words: opt
-                .words
-                .take()
-                .unwrap_or_else(|| DEFAULT_WORDS.to_string())
-                .lines()
-                .map(|x| x.trim().to_string())
-                .filter(|x| !x.is_empty())
-                .collect::<Vec<_>>(),
+            custom_words: Vec::new(),
             only_custom_words: false,
+            custom_words_ratio: opt.custom_words_ratio,
+            word_packs: Vec::new(),
+            game_mode: opt
+                .team_size
+                .filter(|size| *size > 0)
+                .map_or(GameMode::FreeForAll, GameMode::Teams),
+            scoring: opt.scoring,
         }
     }
 }
@@ -0,0 +1,320 @@
+use std::{collections::HashMap, io, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    data::Username,
+    message::{Leaderboard, LeaderboardPeriod, ProfileKey, ProfileStats},
+    utils,
+};
+
+/// number of profiles shown on a leaderboard
+pub const LEADERBOARD_SIZE: usize = 10;
+
+const WEEK_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// version of the stored records, bump on any incompatible change to them
+const STORE_VERSION: u16 = 1;
+
+const VERSION_KEY: &[u8] = b"version";
+
+/// keys of profiles start with this, followed by the profile key
+const PROFILE_PREFIX: u8 = b'p';
+
+/// keys of finished games start with this, followed by when they finished and a unique id
+const GAME_PREFIX: u8 = b'g';
+
+/// how a player did in a finished game
+#[derive(Debug, Clone)]
+pub struct GameResult {
+    pub player: Username,
+    /// profile the player joined the room with, if they have one
+    pub profile: Option<ProfileKey>,
+    pub score: usize,
+    pub won: bool,
+    pub words_guessed: usize,
+}
+
+impl From<&GameResult> for ProfileStats {
+    fn from(result: &GameResult) -> Self {
+        ProfileStats {
+            games_played: 1,
+            wins: result.won as u64,
+            total_score: result.score as u64,
+            words_guessed: result.words_guessed as u64,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Profile {
+    /// name the profile last played as
+    name: String,
+    stats: ProfileStats,
+}
+
+/// games of the last week are kept for the weekly leaderboard
+type FinishedGame = Vec<(ProfileKey, ProfileStats)>;
+
+/// Player profiles kept in a sled database.
+///
+/// Records are written to sled's in-memory log, which is written out in the background, so
+/// recording a game never waits for the disk. Clones share the same database.
+#[derive(Clone)]
+pub struct ProfileStore {
+    db: sled::Db,
+}
+
+impl ProfileStore {
+    /// open the store in the directory `path`, starting a new one if it doesn't exist yet
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let db = sled::open(path)?;
+
+        match db.get(VERSION_KEY)? {
+            Some(version) if version.as_ref() != STORE_VERSION.to_be_bytes() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported profile store version {:?}", version.as_ref()),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                db.insert(VERSION_KEY, &STORE_VERSION.to_be_bytes())?;
+            }
+        }
+
+        Ok(Self { db })
+    }
+
+    /// count a finished game towards the profiles of its players that have one, games that
+    /// dropped out of the last week are forgotten
+    pub fn record_game(&self, results: &[GameResult]) -> io::Result<()> {
+        let results: Vec<(ProfileKey, &GameResult)> = results
+            .iter()
+            .filter_map(|result| Some((result.profile?, result)))
+            .collect();
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        let now = utils::get_time_now();
+        let mut batch = sled::Batch::default();
+
+        for (key, result) in &results {
+            let mut profile = self.profile(*key)?.unwrap_or_else(|| Profile {
+                name: String::new(),
+                stats: ProfileStats::default(),
+            });
+
+            profile.name = result.player.name().to_owned();
+            profile.stats.add(&(*result).into());
+            batch.insert(&profile_key(*key)[..], serialize(&profile)?);
+        }
+
+        let game: FinishedGame = results
+            .iter()
+            .map(|(key, result)| (*key, (*result).into()))
+            .collect();
+        batch.insert(
+            &game_key(now, self.db.generate_id()?)[..],
+            serialize(&game)?,
+        );
+
+        let expired = now.saturating_sub(WEEK_SECS);
+        for key in self.db.range(..=game_key(expired, u64::MAX)).keys() {
+            let key = key?;
+            if key.first() == Some(&GAME_PREFIX) {
+                batch.remove(key);
+            }
+        }
+
+        Ok(self.db.apply_batch(batch)?)
+    }
+
+    /// best profiles by total score, then by wins
+    pub fn leaderboard(&self, period: LeaderboardPeriod) -> io::Result<Leaderboard> {
+        let mut stats: HashMap<ProfileKey, ProfileStats> = HashMap::new();
+
+        match period {
+            LeaderboardPeriod::AllTime => {
+                for entry in self.db.scan_prefix([PROFILE_PREFIX]) {
+                    let (key, profile) = entry?;
+                    let profile: Profile = deserialize(&profile)?;
+                    stats.insert(key_of_profile(&key), profile.stats);
+                }
+            }
+
+            LeaderboardPeriod::Weekly => {
+                let since = utils::get_time_now().saturating_sub(WEEK_SECS);
+
+                for game in self
+                    .db
+                    .range(game_key(since + 1, 0)..=game_key(u64::MAX, u64::MAX))
+                {
+                    let game: FinishedGame = deserialize(&game?.1)?;
+                    for (key, result) in game.iter() {
+                        stats.entry(*key).or_default().add(result);
+                    }
+                }
+            }
+        };
+
+        let mut entries: Vec<(String, ProfileStats)> = Vec::new();
+        for (key, stats) in stats.drain() {
+            if let Some(profile) = self.profile(key)? {
+                entries.push((profile.name, stats));
+            }
+        }
+
+        entries.sort_by(|(a_name, a), (b_name, b)| {
+            (b.total_score, b.wins)
+                .cmp(&(a.total_score, a.wins))
+                .then_with(|| a_name.cmp(b_name))
+        });
+        entries.truncate(LEADERBOARD_SIZE);
+
+        Ok(Leaderboard { period, entries })
+    }
+
+    fn profile(&self, key: ProfileKey) -> io::Result<Option<Profile>> {
+        self.db
+            .get(profile_key(key))?
+            .map(|bytes| deserialize(&bytes))
+            .transpose()
+    }
+}
+
+fn profile_key(key: ProfileKey) -> [u8; 17] {
+    let mut bytes = [PROFILE_PREFIX; 17];
+    bytes[1..].copy_from_slice(&key.to_be_bytes());
+    bytes
+}
+
+fn key_of_profile(bytes: &[u8]) -> ProfileKey {
+    let mut key = [0; 16];
+    key.copy_from_slice(&bytes[1..]);
+    ProfileKey::from_be_bytes(key)
+}
+
+/// games are ordered by when they finished
+fn game_key(finished_at: u64, id: u64) -> [u8; 17] {
+    let mut bytes = [GAME_PREFIX; 17];
+    bytes[1..9].copy_from_slice(&finished_at.to_be_bytes());
+    bytes[9..].copy_from_slice(&id.to_be_bytes());
+    bytes
+}
+
+fn serialize<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+    bincode::serialize(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn deserialize<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+    bincode::deserialize(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// open the store in `dir`, waiting for a store dropped just before to let go of its lock,
+    /// which sled does in the background
+    fn open(dir: &Path) -> io::Result<ProfileStore> {
+        for _ in 0..100 {
+            match ProfileStore::open(dir) {
+                Err(e) if e.to_string().contains("could not acquire lock") => {
+                    std::thread::sleep(std::time::Duration::from_millis(10))
+                }
+                result => return result,
+            }
+        }
+        ProfileStore::open(dir)
+    }
+
+    fn result(profile: ProfileKey, name: &str, score: usize, won: bool) -> GameResult {
+        GameResult {
+            player: Username::new(name.to_owned(), 1),
+            profile: Some(profile),
+            score,
+            won,
+            words_guessed: 2,
+        }
+    }
+
+    #[test]
+    fn totals_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open(dir.path()).unwrap();
+
+        store
+            .record_game(&[result(1, "ann", 300, true), result(2, "bo", 100, false)])
+            .unwrap();
+        store
+            .record_game(&[
+                result(1, "annie", 50, false),
+                result(2, "bo", 400, true),
+                // players without a profile are not counted
+                GameResult {
+                    profile: None,
+                    ..result(3, "guest", 900, false)
+                },
+            ])
+            .unwrap();
+
+        drop(store);
+        let store = open(dir.path()).unwrap();
+        let board = store.leaderboard(LeaderboardPeriod::AllTime).unwrap();
+
+        let expected = |games_played, wins, total_score, words_guessed| ProfileStats {
+            games_played,
+            wins,
+            total_score,
+            words_guessed,
+        };
+        assert_eq!(
+            board.entries,
+            vec![
+                ("bo".to_owned(), expected(2, 1, 500, 4)),
+                ("annie".to_owned(), expected(2, 1, 350, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_board_only_counts_the_last_week() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open(dir.path()).unwrap();
+
+        // a game finished just over a week ago
+        let old_game: FinishedGame = vec![(1, (&result(1, "ann", 300, true)).into())];
+        let finished_at = utils::get_time_now() - WEEK_SECS - 1;
+        store
+            .db
+            .insert(game_key(finished_at, 0), serialize(&old_game).unwrap())
+            .unwrap();
+        store.record_game(&[result(1, "ann", 300, true)]).unwrap();
+        store.record_game(&[result(2, "bo", 100, true)]).unwrap();
+
+        let weekly = store.leaderboard(LeaderboardPeriod::Weekly).unwrap();
+        let all_time = store.leaderboard(LeaderboardPeriod::AllTime).unwrap();
+
+        assert_eq!(store.db.scan_prefix([GAME_PREFIX]).count(), 2);
+        assert_eq!(weekly.entries.len(), 2);
+        assert_eq!(weekly.entries[0].0, "ann");
+        assert_eq!(weekly.entries[0].1.games_played, 1);
+        assert_eq!(all_time.entries.len(), 2);
+        assert_eq!(all_time.entries[0].1.games_played, 1);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open(dir.path()).unwrap();
+        store.db.insert(VERSION_KEY, &[0, 0]).unwrap();
+        drop(store);
+
+        assert!(matches!(
+            open(dir.path()),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+}
@@ -1,41 +1,88 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
 
 use GameEvent::StateUpdate;
 
 use crate::{
-    data::{GameState, TurnPhase, UserId, Username, WordHint},
+    data::{GameMode, GameState, TurnPhase, UserId, Username, WordHint},
     events::{EventQueue, EventSender},
-    message::{ChatMessage, Draw, GameEvent, InitialRoomState, RoomEvent, RoomInfo},
+    export::{Drawing, Gallery},
+    message::{
+        ChatMessage, Draw, GameEvent, InitialRoomState, LeaderCommand, ProfileKey, ResumeToken,
+        RoomEvent, RoomInfo, DRAW_BATCH_MILLIS,
+    },
     // utils,
+    replay::ReplayRecorder,
 };
 
 use super::{
+    cli,
     session::{
-        Message::{RoomClosed, RoomEvent as Event, RoomJoined},
+        Message::{KickFromRoom, RoomClosed, RoomEvent as Event, RoomJoined},
         UserSessionInbox,
     },
     skribbl::SkribblState,
-    Error, GameOpts, Result,
+    word_pack::WordPack,
+    Error, GameOpts, Message as ServerMessage, Result,
 };
 
 const REQUIRED_PLAYERS: usize = 2;
 
+/// max number of players matchmaking will place in a room
+pub const MAX_ROOM_SIZE: usize = 8;
+
+/// number of seconds a player that lost connection keeps their place in the room
+pub const RECONNECT_GRACE_SECS: u64 = 30;
+
 pub type RoomInbox = EventSender<RoomMessage>;
 
 /// List of messages a game room can recieve
 pub enum RoomMessage {
-    /// Notify room of player join
+    /// Notify room of player join, with the profile they play as
     Join {
         name: Username,
         inbox: UserSessionInbox,
+        profile: Option<ProfileKey>,
+    },
+
+    /// Notify room of user joining as spectator
+    Spectate {
+        name: Username,
+        inbox: UserSessionInbox,
     },
 
-    /// Notify room of player leaving
-    Leave { name: Username },
+    /// Notify room of player leaving, sent by the session with the given id
+    Leave { name: Username, session: UserId },
+
+    /// Notify room of player that lost connection, their place is kept for a while
+    Disconnect { name: Username, session: UserId },
+
+    /// Notify room of player reconnecting with a resume token, through the session with the
+    /// given id
+    Resume {
+        token: ResumeToken,
+        session: UserId,
+        inbox: UserSessionInbox,
+    },
+
+    /// Remove a disconnected player if they have not resumed by now
+    ResumeTimeout { name: Username },
 
     /// Notify room of draw message
     Draw { from: Username, draw: Draw },
 
+    /// Notify room of the word the drawing player chose
+    ChooseWord { from: Username, word: String },
+
+    /// Notify room of a command sent by the room leader
+    LeaderCommand { from: Username, cmd: LeaderCommand },
+
     /// Notify room of player chat
     Chat { from: Username, msg: String },
 
@@ -44,6 +91,9 @@ pub enum RoomMessage {
 
     /// Do a game tick
     Tick,
+
+    /// Send on the paints coalesced since the last flush
+    FlushDraws,
 }
 
 pub struct GameRoom {
@@ -56,17 +106,52 @@ pub struct GameRoom {
     /// options of this room
     game_opts: GameOpts,
 
-    /// The main server thread creates the shared Vec of words and passes a reference to the rooms.
-    shared_server_words: Arc<Vec<String>>,
+    /// The main server thread loads the word packs and passes a reference to the rooms.
+    word_packs: Arc<Vec<WordPack>>,
 
     /// holds all sessions connected to this room
     sessions: HashMap<Username, UserSessionInbox>,
 
+    /// holds sessions watching this room, these never take part in the game
+    spectators: HashMap<Username, UserSessionInbox>,
+
+    /// resume token of every user in this room
+    resume_tokens: HashMap<Username, ResumeToken>,
+
+    /// users that lost connection and can still resume, by when they lost it
+    disconnected: HashMap<Username, Instant>,
+
+    /// profiles players joined with, kept until the game ends so players that left still get
+    /// their results
+    profiles: HashMap<Username, ProfileKey>,
+
+    /// id of the session a resumed user is connected through, the one they joined with is
+    /// the id of their username
+    resumed_sessions: HashMap<Username, UserId>,
+
     /// event queue for this room loop
     event_queue: EventQueue<RoomMessage>,
 
+    /// sender for server event queue
+    server: EventSender<ServerMessage>,
+
+    /// where finished drawings are saved, if the server keeps them
+    gallery: Option<Gallery>,
+
+    /// directory games are recorded to, if the server keeps replays
+    replays: Option<PathBuf>,
+
+    /// recorder of the running game's broadcasts
+    recorder: Option<ReplayRecorder>,
+
     /// game struct
     skribbl: Option<SkribblState>,
+
+    /// paints of the drawing player not sent to the others yet, consecutive ones are merged
+    pending_paint: Option<(UserId, Draw)>,
+
+    /// number of joins handled, reported to the server so it knows which ones are still coming
+    joins_handled: usize,
 }
 
 impl From<GameState> for RoomEvent {
@@ -81,17 +166,31 @@ impl GameRoom {
     pub fn new(
         key: String,
         game_opts: GameOpts,
-        server_words: &Arc<Vec<String>>,
+        word_packs: &Arc<Vec<WordPack>>,
         leader: Option<Username>,
+        server: EventSender<ServerMessage>,
+        gallery: Option<Gallery>,
+        replays: Option<PathBuf>,
     ) -> Self {
         Self {
             key,
             leader,
             game_opts,
-            shared_server_words: Arc::clone(server_words),
+            word_packs: Arc::clone(word_packs),
             sessions: HashMap::new(),
+            spectators: HashMap::new(),
+            resume_tokens: HashMap::new(),
+            disconnected: HashMap::new(),
+            profiles: HashMap::new(),
+            resumed_sessions: HashMap::new(),
             event_queue: EventQueue::default(),
+            server,
+            gallery,
+            replays,
+            recorder: None,
             skribbl: None,
+            pending_paint: None,
+            joins_handled: 0,
         }
     }
 
@@ -99,17 +198,29 @@ impl GameRoom {
 
     pub fn sender(&self) -> &EventSender<RoomMessage> { self.event_queue.sender() }
 
+    /// get the session of a player or spectator
+    fn inbox(&self, name: &Username) -> Option<&UserSessionInbox> {
+        self.sessions
+            .get(name)
+            .or_else(|| self.spectators.get(name))
+    }
+
+    /// all sessions in this room, players and spectators
+    fn all_sessions(&self) -> impl Iterator<Item = (&Username, &UserSessionInbox)> {
+        self.sessions.iter().chain(self.spectators.iter())
+    }
+
     /// send a `RoomEvent` to a specific session
     fn send<E: Into<RoomEvent>>(&self, name: Username, event: E) {
         let event = event.into();
-        if let Some(session) = self.sessions.get(&name) {
+        if let Some(session) = self.inbox(&name) {
             session.send(Event(event));
         }
     }
 
     /// send a `ChatMessage::System` to a specific session
     fn send_system_msg<T: Into<String>>(&self, user: Username, msg: T) {
-        if let Some(session) = self.sessions.get(&user) {
+        if let Some(session) = self.inbox(&user) {
             session.send(Event(RoomEvent::Chat(ChatMessage::System(msg.into()))));
         }
     }
@@ -122,30 +233,85 @@ impl GameRoom {
         self.broadcast(RoomEvent::Chat(ChatMessage::System(msg)))
     }
 
-    /// broadcast a `RoomEvent` to all connected players
+    /// broadcast a `RoomEvent` to all connected players and spectators
     fn broadcast<E: Into<RoomEvent>>(&self, event: E) {
         let event = event.into();
-        for (name, session) in self.sessions.iter() {
+        self.record(&event);
+        for (name, session) in self.all_sessions() {
             session.send(Event(event.clone()));
         }
     }
 
-    /// broadcast a `RoomEvent` to all connected players excluding given player
+    /// broadcast a `RoomEvent` to all connected players and spectators excluding given player
     fn broadcast_except<E: Into<RoomEvent>>(&mut self, event: E, except: UserId) {
         let event = event.into();
-        for (_, session) in self.sessions.iter_mut().filter(|(n, _)| n.id() != except) {
+        self.record(&event);
+        for (_, session) in self.all_sessions().filter(|(n, _)| n.id() != except) {
             session.send(Event(event.clone()));
         }
     }
 
+    /// add a broadcast event to the replay of the running game
+    fn record(&self, event: &RoomEvent) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(event);
+        }
+    }
+
     fn users(&self) -> Vec<Username> { self.sessions.keys().cloned().collect() }
 
+    fn get_user(&self, id: UserId) -> Option<Username> {
+        self.all_sessions()
+            .map(|(name, _)| name)
+            .find(|name| name.id() == id)
+            .cloned()
+    }
+
+    fn is_leader(&self, name: &Username) -> bool { self.leader.as_ref() == Some(name) }
+
+    /// whether the session with the given id is the one a user is currently connected through,
+    /// sessions taken over by a resume may still report leaving
+    fn is_current_session(&self, name: &Username, session: UserId) -> bool {
+        self.resumed_sessions
+            .get(name)
+            .copied()
+            .unwrap_or(name.id())
+            == session
+    }
+
+    /// players needed to start a game, a team game needs at least two teams
+    fn min_players(&self) -> usize {
+        match self.game_opts.game_mode {
+            GameMode::Teams(team_size) => (team_size + 1).max(REQUIRED_PLAYERS),
+            GameMode::FreeForAll => REQUIRED_PLAYERS,
+        }
+    }
+
+    /// whether new players can be placed in this room
+    fn is_joinable(&self) -> bool { self.skribbl.is_none() && self.sessions.len() < MAX_ROOM_SIZE }
+
+    /// report fill level and joinable status of this room to the server
+    fn notify_server_status(&self) {
+        self.server.send(ServerMessage::RoomStatus {
+            key: self.key.clone(),
+            num_of_users: self.sessions.len(),
+            joins_handled: self.joins_handled,
+            is_joinable: self.is_joinable(),
+        });
+    }
+
+    /// ask the server to close this room, it keeps running until everything sent to it before
+    /// is handled
+    fn close(&self) { self.server.send(ServerMessage::CloseRoom(self.key.clone())) }
+
     fn info(&self) -> RoomInfo {
         RoomInfo {
             key: self.key.clone(),
             connected_users: self.users(),
+            spectators: self.spectators.keys().cloned().collect(),
             game_opts: self.game_opts.clone(),
             leader: self.leader.clone(),
+            word_packs: self.word_packs.iter().map(WordPack::info).collect(),
         }
     }
 
@@ -159,12 +325,22 @@ impl GameRoom {
             return;
         }
 
+        if let Some(dir) = &self.replays {
+            match ReplayRecorder::create(dir, self.info()) {
+                Ok((recorder, path)) => {
+                    log::info!("({}) recording game to {}", self.key, path.display());
+                    self.recorder = Some(recorder);
+                }
+                Err(e) => log::error!("({}) could not record game: {}", self.key, e),
+            }
+        }
+
         self.skribbl = Some({
             // create game with current game_opts
             let game = SkribblState::new(
                 self.game_opts.clone(),
                 self.users(),
-                Arc::clone(&self.shared_server_words),
+                Arc::clone(&self.word_packs),
             );
 
             // broadcast this game to all players
@@ -173,6 +349,8 @@ impl GameRoom {
             game
         });
 
+        self.notify_server_status();
+
         // start game ticks
         self.event_queue
             .sender()
@@ -180,7 +358,15 @@ impl GameRoom {
     }
 
     fn end_game(&mut self) {
-        if self.skribbl.take().is_some() {
+        if let Some(game) = self.skribbl.take() {
+            if let GameState::Finish = game.info.state {
+                self.server
+                    .send(ServerMessage::GameFinished(game.results(&self.profiles)));
+            }
+
+            let sessions = &self.sessions;
+            self.profiles.retain(|name, _| sessions.contains_key(name));
+
             if self
                 .leader
                 .as_ref()
@@ -190,9 +376,11 @@ impl GameRoom {
                 self.broadcast(RoomEvent::EndGame);
             } else {
                 // close this room if does not have a leader that is still connected
-                self.sender().send_with_urgency(RoomMessage::Close);
+                self.close();
             }
 
+            self.notify_server_status();
+            self.recorder = None; // ends the replay file
             log::debug!("Ending game room {}.", self.key);
         } else {
             log::warn!("tried to end game in room {} with no game.", self.key);
@@ -202,50 +390,94 @@ impl GameRoom {
     fn on_paint_msg(&mut self, sender: Username, draw: Draw) {
         if let Some(ref mut game) = self.skribbl {
             // only process draw message from player that can draw
-            if !game.is_drawing(sender.id()) {
+            if !game.is_drawing(sender.id()) || !draw.is_valid(game.info.dimensions) {
                 return; // naughty client
             }
 
-            // update server game state
-            let canvas = &mut game.info.canvas;
-            match &draw {
-                Draw::Clear => canvas.clear(),
-                Draw::Paint { points, color } => {
-                    for point in points {
-                        canvas.insert(*point, *color);
+            match draw {
+                Draw::Undo | Draw::Redo => {
+                    let patch = if let Draw::Undo = draw {
+                        game.undo()
+                    } else {
+                        game.redo()
+                    };
+
+                    // the drawer doesn't know the stroke log, so it gets the change too
+                    if let Some(patch) = patch.filter(|patch| !patch.is_empty()) {
+                        self.broadcast(GameEvent::CanvasPatch(patch));
                     }
                 }
-                Draw::Erase(point) => {
-                    canvas.remove(point);
+
+                Draw::EndStroke => game.draw(draw),
+
+                Draw::Paint { .. } => {
+                    game.draw(draw.clone());
+                    self.queue_paint(sender.id(), draw);
                 }
-            };
 
-            self.broadcast_except(RoomEvent::GameEvent(GameEvent::Draw(draw)), sender.id());
+                _ => {
+                    // update server game state
+                    game.draw(draw.clone());
+
+                    self.broadcast_except(RoomEvent::GameEvent(GameEvent::Draw(draw)), sender.id());
+                }
+            }
+        }
+    }
+
+    /// hold back a paint for a moment, so the paints of a stroke go out as one
+    fn queue_paint(&mut self, from: UserId, draw: Draw) {
+        if let Some((pending_from, pending)) = &mut self.pending_paint {
+            if *pending_from == from && pending.coalesce(&draw) {
+                return;
+            }
+        }
+
+        self.flush_draws();
+        self.pending_paint = Some((from, draw));
+        self.event_queue.sender().send_with_delay(
+            RoomMessage::FlushDraws,
+            Duration::from_millis(DRAW_BATCH_MILLIS),
+        );
+    }
+
+    fn flush_draws(&mut self) {
+        if let Some((from, draw)) = self.pending_paint.take() {
+            self.broadcast_except(RoomEvent::GameEvent(GameEvent::Draw(draw)), from);
         }
     }
 
     fn on_chat_msg(&mut self, sender: Username, chat_msg: String) {
         if let Some(ref mut game) = self.skribbl {
-            // whether the given player can guess in the current turn.
-            if game.can_player_guess(game.get_player(&sender).unwrap()) {
+            // whether the given player can guess in the current turn, spectators never can.
+            let can_guess = game
+                .get_player(&sender)
+                .is_some_and(|player| game.can_player_guess(player));
+
+            // a guess is only checked while the word is drawn, other chat goes to everyone
+            if can_guess {
                 match game.do_guess(&sender, &chat_msg) {
                     // TODO: on correct guess, let users know that score has gone up?
-                    0 => self.broadcast_system_msg(format!("{} guessed it!", sender)),
+                    0 => {
+                        self.broadcast(GameEvent::PlayerGuessed(sender.clone()));
+                        self.broadcast_system_msg(format!("{} guessed it!", sender))
+                    }
 
                     1 => self.send_system_msg(sender, "You're very close!".to_string()),
                     _ => self.broadcast_msg(ChatMessage::User(sender, chat_msg)),
                 };
             } else {
-                // player cannot guess, send message to all users who can't
+                // user cannot guess, send message to all users who can't
                 let msg = RoomEvent::Chat(ChatMessage::User(sender, chat_msg));
 
-                for player in game
+                for user in game
                     .get_non_guessing_players()
                     .iter()
                     .map(|pl| pl.name.clone())
+                    .chain(self.spectators.keys().cloned())
                     .collect::<Vec<Username>>()
                 {
-                    self.send(player, msg.clone());
+                    self.send(user, msg.clone());
                 }
             }
         } else {
@@ -255,6 +487,18 @@ impl GameRoom {
     }
 
     fn on_user_leave(&mut self, username: Username) {
+        self.resume_tokens.remove(&username);
+        self.disconnected.remove(&username);
+        self.resumed_sessions.remove(&username);
+        if self.skribbl.is_none() {
+            self.profiles.remove(&username);
+        }
+
+        if self.spectators.remove(&username).is_some() {
+            log::info!("({}) {} stopped spectating.", self.key, username);
+            return self.broadcast(RoomEvent::RoomUpdate(self.info()));
+        }
+
         if self.sessions.remove(&username).is_some() {
             let id = username.id();
 
@@ -262,13 +506,34 @@ impl GameRoom {
 
             // maybe let the client handle the message?
             self.broadcast_system_msg(format!("{} left the room", username));
+
+            if self.is_leader(&username) {
+                // promote another player instead of closing the room, one that is still here
+                let new_leader = self
+                    .sessions
+                    .keys()
+                    .filter(|name| !self.disconnected.contains_key(name))
+                    .min()
+                    .cloned();
+
+                if let Some(new_leader) = new_leader {
+                    self.broadcast_system_msg(format!("{} is now the room leader", new_leader));
+                    self.leader = Some(new_leader);
+                    self.broadcast(RoomEvent::RoomUpdate(self.info()));
+                } else {
+                    // nobody left to lead this room
+                    self.close();
+                }
+            }
+
             self.broadcast(RoomEvent::UserLeave(username));
 
+            let min_players = self.min_players();
             if let Some(ref mut game) = self.skribbl {
                 if self.sessions.is_empty() {
                     self.end_game();
-                } else if self.sessions.len() < REQUIRED_PLAYERS {
-                    // stop game when there isnt enough players
+                } else if self.sessions.len() < min_players {
+                    // stop game when there isnt enough players, or a team game is down to one team
                     game.end();
                     let state = game.info.state.clone();
                     self.broadcast(StateUpdate(state));
@@ -277,11 +542,145 @@ impl GameRoom {
                     self.start_next_turn();
                 }
             }
+
+            self.notify_server_status();
+        }
+    }
+
+    fn send_initial_state(
+        &self,
+        username: Username,
+        inbox: &UserSessionInbox,
+        resume_token: Option<ResumeToken>,
+    ) {
+        inbox.send_with_urgency(RoomJoined(
+            self.sender().clone(),
+            InitialRoomState {
+                username,
+                room: self.info(),
+                game: self.skribbl.as_ref().map(|game| game.info.clone()),
+                is_spectator: resume_token.is_none(),
+                resume_token,
+            },
+        ));
+    }
+
+    fn on_spectator_join(&mut self, username: Username, inbox: UserSessionInbox) {
+        if self.inbox(&username).is_some() {
+            return log::warn!(
+                "{} tried to spectate room `{}` they are already in",
+                username,
+                self.key
+            );
+        }
+
+        log::info!("({}) {} is spectating.", self.key, username);
+
+        self.send_initial_state(username.clone(), &inbox, None);
+        self.spectators.insert(username.clone(), inbox);
+
+        self.broadcast_system_msg(format!("{} is spectating", username));
+        self.broadcast(RoomEvent::RoomUpdate(self.info()));
+    }
+
+    /// keep the place of a user that lost connection, so they can resume
+    fn on_user_disconnect(&mut self, username: Username) {
+        if self.spectators.contains_key(&username) {
+            // spectators have no place to keep
+            return self.on_user_leave(username);
+        } else if !self.sessions.contains_key(&username) {
+            return;
+        }
+
+        log::info!("({}) {} lost connection.", self.key, username);
+
+        self.broadcast_system_msg(format!("{} lost connection", username));
+        self.disconnected.insert(username.clone(), Instant::now());
+
+        self.sender().send_with_delay(
+            RoomMessage::ResumeTimeout { name: username },
+            Duration::from_secs(RECONNECT_GRACE_SECS),
+        );
+    }
+
+    fn on_resume_timeout(&mut self, username: Username) {
+        let grace_period = Duration::from_secs(RECONNECT_GRACE_SECS);
+        let timed_out = self
+            .disconnected
+            .get(&username)
+            .is_some_and(|since| since.elapsed() >= grace_period);
+
+        if timed_out {
+            self.on_user_leave(username);
+        }
+    }
+
+    /// let a user back in through a new session, the client may notice the connection dropped
+    /// before the room does, so their old session is taken over if it is still here
+    fn on_user_resume(&mut self, token: ResumeToken, session: UserId, inbox: UserSessionInbox) {
+        let username = self
+            .resume_tokens
+            .iter()
+            .find(|(_, t)| **t == token)
+            .map(|(name, _)| name.clone())
+            .filter(|name| self.sessions.contains_key(name));
+
+        let username = match username {
+            Some(username) => username,
+            None => {
+                return inbox.send_with_urgency(KickFromRoom(
+                    "Could not resume your session in this room".to_owned(),
+                ))
+            }
+        };
+
+        log::info!("({}) {} reconnected.", self.key, username);
+
+        if self.disconnected.remove(&username).is_none() {
+            // the old session has not noticed it lost the client yet
+            if let Some(stale) = self.sessions.get(&username) {
+                stale.send_with_urgency(KickFromRoom(
+                    "Your session was resumed from another connection".to_owned(),
+                ));
+            }
+        }
+        self.resumed_sessions.insert(username.clone(), session);
+        self.send_initial_state(username.clone(), &inbox, Some(token));
+
+        // drawing player has to get the word again
+        if let Some(ref game) = self.skribbl {
+            if let GameState::Playing(turn) = &game.info.state {
+                if turn.who_is_drawing == username.id() {
+                    let mut turn = turn.clone();
+                    turn.phase = match turn.phase {
+                        TurnPhase::ChoosingWord(_) => {
+                            TurnPhase::ChoosingWord(game.word_choices().to_vec())
+                        }
+                        TurnPhase::Drawing(_) => {
+                            TurnPhase::Drawing(WordHint::Draw(game.word().to_owned()))
+                        }
+                        phase => phase,
+                    };
+
+                    inbox.send(Event(StateUpdate(GameState::Playing(turn)).into()));
+                }
+            }
         }
+
+        self.sessions.insert(username.clone(), inbox);
+        self.broadcast_system_msg(format!("{} reconnected", username));
     }
 
-    fn on_user_join(&mut self, username: Username, inbox: UserSessionInbox) {
-        if self.sessions.contains_key(&username) {
+    fn on_user_join(
+        &mut self,
+        username: Username,
+        inbox: UserSessionInbox,
+        profile: Option<ProfileKey>,
+    ) {
+        self.joins_handled += 1;
+
+        if self.inbox(&username).is_some() {
+            self.notify_server_status();
             return log::warn!(
                 "{} tried to join room `{}` they are already in",
                 username,
@@ -289,15 +688,14 @@ impl GameRoom {
             );
         }
 
+        let resume_token = rand::thread_rng().gen();
+        self.resume_tokens.insert(username.clone(), resume_token);
+        if let Some(profile) = profile {
+            self.profiles.insert(username.clone(), profile);
+        }
+
         // send joining player initial game state
-        inbox.send_with_urgency(RoomJoined(
-            self.sender().clone(),
-            InitialRoomState {
-                username: username.clone(),
-                room: self.info(),
-                game: self.skribbl.as_ref().map(|game| game.info.clone()),
-            },
-        ));
+        self.send_initial_state(username.clone(), &inbox, Some(resume_token));
 
         let join_msg = format!("{} joined", username);
 
@@ -309,8 +707,81 @@ impl GameRoom {
         self.broadcast_system_msg(join_msg);
 
         // start game if there are enough players and no room leader
-        if self.leader.is_none() && self.sessions.len() >= REQUIRED_PLAYERS {
+        if self.leader.is_none() && self.sessions.len() >= self.min_players() {
             self.start_game()
+        } else {
+            self.notify_server_status();
+        }
+    }
+
+    fn on_leader_command(&mut self, sender: Username, cmd: LeaderCommand) {
+        if !self.is_leader(&sender) {
+            return self.send_system_msg(sender, "Only the room leader can do that.");
+        }
+
+        match cmd {
+            LeaderCommand::StartGame => {
+                if self.skribbl.is_some() {
+                    self.send_system_msg(sender, "The game has already started.")
+                } else if self.sessions.len() < self.min_players() {
+                    let min_players = self.min_players();
+                    self.send_system_msg(
+                        sender,
+                        format!("At least {} players are needed to start.", min_players),
+                    )
+                } else if self.game_opts.only_custom_words && self.game_opts.custom_words.is_empty()
+                {
+                    self.send_system_msg(sender, "Add some custom words before starting.")
+                } else {
+                    self.start_game()
+                }
+            }
+
+            LeaderCommand::SetGameOpts(opts) => {
+                if self.skribbl.is_some() {
+                    self.send_system_msg(sender, "Options cannot be changed during a game.")
+                } else if opts.number_of_rounds == 0
+                    || opts.draw_time == 0
+                    || opts.custom_words_ratio > 100
+                    || opts.game_mode == GameMode::Teams(0)
+                    || opts
+                        .word_packs
+                        .iter()
+                        .any(|name| !self.word_packs.iter().any(|pack| &pack.name == name))
+                    || !cli::valid_dimensions(opts.dimensions)
+                {
+                    self.send_system_msg(sender, "Invalid game options.")
+                } else {
+                    self.game_opts = opts;
+                    self.broadcast(RoomEvent::RoomUpdate(self.info()));
+                }
+            }
+
+            LeaderCommand::Kick(id) => {
+                if let Some(username) = self.get_user(id).filter(|name| name != &sender) {
+                    if let Some(session) = self.inbox(&username) {
+                        session.send_with_urgency(KickFromRoom(
+                            "You were kicked by the room leader".to_owned(),
+                        ));
+                    }
+
+                    self.broadcast_system_msg(format!("{} was kicked", username));
+                    self.on_user_leave(username);
+                }
+            }
+
+            LeaderCommand::SetLeader(id) => {
+                // spectators and players that lost connection can't lead the room
+                let username = self.get_user(id).filter(|name| {
+                    self.sessions.contains_key(name) && !self.disconnected.contains_key(name)
+                });
+
+                if let Some(username) = username {
+                    self.broadcast_system_msg(format!("{} is now the room leader", username));
+                    self.leader = Some(username);
+                    self.broadcast(RoomEvent::RoomUpdate(self.info()));
+                }
+            }
         }
     }
 
@@ -325,11 +796,38 @@ impl GameRoom {
         game.end_turn(timed_out);
 
         let game_state = game.info.state.clone();
+        self.save_drawing();
 
         // broadcast state
         self.broadcast(game_state);
     }
 
+    /// save the drawing of the turn that was just revealed to the gallery, off the room loop
+    fn save_drawing(&self) {
+        let (gallery, game) = match (&self.gallery, &self.skribbl) {
+            (Some(gallery), Some(game)) if !game.info.canvas.is_empty() => (gallery.clone(), game),
+            _ => return,
+        };
+
+        let word = match game.info.state.as_turn().map(|t| &t.phase) {
+            Some(TurnPhase::RevealWord { word, .. }) => word.clone(),
+            _ => return,
+        };
+
+        let drawing = Drawing {
+            key: self.key.clone(),
+            round: game.info.round_num,
+            word,
+            dimensions: game.info.dimensions,
+            canvas: game.info.canvas.clone(),
+        };
+
+        tokio::task::spawn_blocking(move || match gallery.save(&drawing) {
+            Ok(path) => log::info!("({}) saved drawing to {}", drawing.key, path.display()),
+            Err(e) => log::error!("({}) could not save drawing: {}", drawing.key, e),
+        });
+    }
+
     fn choose_word(&mut self, choice: Option<String>) {
         let skribbl = match &mut self.skribbl {
             Some(it) => it,
@@ -337,17 +835,22 @@ impl GameRoom {
         };
 
         let maybe_turn_phase = skribbl.info.state.as_turn().map(|t| &t.phase);
-        if let Some(TurnPhase::ChoosingWord(choices)) = maybe_turn_phase {
-            let choice = choice.filter(|c| choices.contains(c));
-
+        if let Some(TurnPhase::ChoosingWord(_)) = maybe_turn_phase {
             skribbl.choose_draw_word(choice);
 
             let word_to_draw = skribbl.word().to_owned();
-            let mut turn = skribbl.info.state.as_turn().cloned().unwrap();
+            let mut turn = match skribbl.info.state.as_turn().cloned() {
+                Some(turn) => turn,
+                None => {
+                    // game ended as there was no word to draw
+                    let game_state = skribbl.info.state.clone();
+                    return self.broadcast(StateUpdate(game_state));
+                }
+            };
             let who_is_drawing = turn.who_is_drawing;
 
             // clear canvas
-            skribbl.info.canvas.clear();
+            skribbl.clear_canvas();
             self.broadcast(GameEvent::Draw(Draw::Clear));
             self.broadcast_except(
                 StateUpdate(GameState::Playing(turn.clone())),
@@ -365,6 +868,17 @@ impl GameRoom {
         }
     }
 
+    fn on_choose_word(&mut self, sender: Username, word: String) {
+        if let Some(ref game) = self.skribbl {
+            // only the drawing player may choose the word
+            if !game.is_drawing(sender.id()) {
+                return; // naughty client
+            }
+
+            self.choose_word(Some(word));
+        }
+    }
+
     fn start_next_turn(&mut self) {
         if let Some(ref mut game) = self.skribbl {
             if game.has_round_ended() {
@@ -377,7 +891,7 @@ impl GameRoom {
                 let game_state = game.info.state.clone();
                 self.broadcast(StateUpdate(game_state));
             } else {
-                game.start_next_turn();
+                let has_words_left = game.start_next_turn();
                 let game_state = game.info.state.clone();
 
                 if let Some(who_is_drawing) = game.info.who_is_drawing() {
@@ -386,7 +900,30 @@ impl GameRoom {
                     log::debug!("(#{}) Finshing game (showing winners) ..", self.key,);
                 }
 
-                self.broadcast(StateUpdate(game_state));
+                if let GameState::Playing(mut turn) = game_state {
+                    let who_is_drawing = turn.who_is_drawing;
+                    let choices = game.word_choices().to_vec();
+
+                    // only let the drawing player see the words to choose from
+                    self.broadcast_except(
+                        StateUpdate(GameState::Playing(turn.clone())),
+                        who_is_drawing,
+                    );
+                    self.send(
+                        ("", who_is_drawing).into(), // id -> username
+                        GameState::Playing({
+                            turn.phase = TurnPhase::ChoosingWord(choices);
+                            turn
+                        }),
+                    );
+                } else {
+                    if !has_words_left {
+                        log::debug!("(#{}) Ran out of words ..", self.key);
+                        self.broadcast_system_msg("No words left to draw, ending the game.".into());
+                    }
+
+                    self.broadcast(StateUpdate(game_state));
+                }
             }
         }
     }
@@ -418,11 +955,11 @@ impl GameRoom {
                 .info
                 .players
                 .iter()
-                .filter(|pl| who_is_drawing != pl.name.id() && pl.secs_to_solve_turn == 0)
+                .filter(|pl| game.can_player_guess(pl))
                 .count();
 
             if players_left_to_guess == 0 {
-                game.end_turn(false);
+                self.reveal_word(false);
             } else if let Some(revealed) = game.reveal_random_char() {
                 self.broadcast_except(GameEvent::WordHint(revealed), who_is_drawing);
             }
@@ -434,28 +971,341 @@ impl GameRoom {
             .send_with_delay(RoomMessage::Tick, Duration::from_secs(1));
     }
 
+    /// handle a message of the room queue, returns false once the room is closed
+    fn on_message(&mut self, msg: RoomMessage) -> bool {
+        // nothing may overtake the paints waiting to be sent
+        if !matches!(
+            msg,
+            RoomMessage::Draw {
+                draw: Draw::Paint { .. },
+                ..
+            }
+        ) {
+            self.flush_draws();
+        }
+
+        match msg {
+            RoomMessage::FlushDraws => {} // flushed above
+            RoomMessage::Tick => self.on_tick(),
+            RoomMessage::Join {
+                name,
+                inbox,
+                profile,
+            } => self.on_user_join(name, inbox, profile),
+            RoomMessage::Spectate { name, inbox } => self.on_spectator_join(name, inbox),
+            RoomMessage::Leave { name, session } | RoomMessage::Disconnect { name, session }
+                if !self.is_current_session(&name, session) =>
+            {
+                log::debug!("({}) ignoring the replaced session of {}", self.key, name)
+            }
+            RoomMessage::Leave { name, .. } => self.on_user_leave(name),
+            RoomMessage::Disconnect { name, .. } => self.on_user_disconnect(name),
+            RoomMessage::Resume {
+                token,
+                session,
+                inbox,
+            } => self.on_user_resume(token, session, inbox),
+            RoomMessage::ResumeTimeout { name } => self.on_resume_timeout(name),
+            RoomMessage::Draw { from, draw } => self.on_paint_msg(from, draw),
+            RoomMessage::ChooseWord { from, word } => self.on_choose_word(from, word),
+            RoomMessage::LeaderCommand { from, cmd } => self.on_leader_command(from, cmd),
+            RoomMessage::Chat { from, msg } => self.on_chat_msg(from, msg),
+            RoomMessage::Close => return false,
+        }
+
+        true
+    }
+
     /// blocking loop
     pub async fn run_loop(&mut self) -> Result<()> {
         loop {
-            match self
+            let msg = self
                 .event_queue
                 .recv_async()
                 .await
-                .ok_or(Error::EmptyOptional)?
-            {
-                RoomMessage::Tick => self.on_tick(),
-                RoomMessage::Join { name, inbox } => self.on_user_join(name, inbox),
-                RoomMessage::Leave { name } => self.on_user_leave(name),
-                RoomMessage::Draw { from, draw } => self.on_paint_msg(from, draw),
-                RoomMessage::Chat { from, msg } => self.on_chat_msg(from, msg),
-                RoomMessage::Close => break,
+                .ok_or(Error::EmptyOptional)?;
+
+            if !self.on_message(msg) {
+                break;
             }
         }
 
-        for (_, session) in self.sessions.drain() {
+        for (_, session) in self.sessions.drain().chain(self.spectators.drain()) {
             session.send_with_urgency(RoomClosed)
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{Color, Scoring},
+        message::Brush,
+        server::session::Message as SessionMessage,
+    };
+
+    fn paint(x: u16, color: Color) -> Draw {
+        Draw::Paint {
+            points: vec![(x, 0)],
+            color,
+            brush: Brush::default(),
+        }
+    }
+
+    fn player(id: UserId) -> Username { Username::new(format!("player{}", id), id) }
+
+    /// a room of the given number of players led by the first, with the server queue and the
+    /// queue of every player
+    fn room(
+        players: UserId,
+    ) -> (
+        GameRoom,
+        EventQueue<ServerMessage>,
+        Vec<EventQueue<SessionMessage>>,
+    ) {
+        let opts = GameOpts {
+            dimensions: cli::DEFAULT_DIMENSIONS,
+            number_of_rounds: 3,
+            draw_time: 80,
+            custom_words: Vec::new(),
+            only_custom_words: false,
+            custom_words_ratio: 0,
+            word_packs: Vec::new(),
+            game_mode: GameMode::FreeForAll,
+            scoring: Scoring::Classic,
+        };
+        let packs = Arc::new(vec![WordPack::from_word_list(
+            "default",
+            "cat\ndog\ntree\nhouse\nboat\nsun",
+        )]);
+        let server = EventQueue::<ServerMessage>::default();
+        let mut room = GameRoom::new(
+            "key".to_owned(),
+            opts,
+            &packs,
+            Some(player(0)),
+            server.sender().clone(),
+            None,
+            None,
+        );
+
+        let queues: Vec<_> = (0..players).map(|_| EventQueue::default()).collect();
+        for (id, queue) in queues.iter().enumerate() {
+            room.sessions
+                .insert(player(id as UserId), queue.sender().clone());
+        }
+
+        (room, server, queues)
+    }
+
+    /// a room in the drawing phase of a game between two players, with the queue of the player
+    /// that guesses
+    fn drawing_room() -> (GameRoom, Username, EventQueue<SessionMessage>) {
+        let (mut room, _, queues) = room(2);
+
+        let mut game = SkribblState::new(
+            room.game_opts.clone(),
+            room.users(),
+            Arc::clone(&room.word_packs),
+        );
+        game.start_next_turn();
+        game.choose_draw_word(None);
+        let drawer = game.info.who_is_drawing().unwrap().clone();
+        room.skribbl = Some(game);
+
+        let guesser = queues.into_iter().nth(1 - drawer.id() as usize).unwrap();
+        (room, drawer, guesser)
+    }
+
+    fn received(queue: &mut EventQueue<SessionMessage>) -> GameEvent {
+        match queue.recv() {
+            Event(RoomEvent::GameEvent(event)) => event,
+            _ => panic!("expected a game event"),
+        }
+    }
+
+    #[test]
+    fn paints_of_a_stroke_are_sent_as_one() {
+        let (mut room, drawer, mut guesser) = drawing_room();
+
+        for x in 0..3 {
+            room.on_message(RoomMessage::Draw {
+                from: drawer.clone(),
+                draw: paint(x, Color::Red),
+            });
+        }
+        room.on_message(RoomMessage::FlushDraws);
+
+        match received(&mut guesser) {
+            GameEvent::Draw(Draw::Paint { points, .. }) => {
+                assert_eq!(points, vec![(0, 0), (1, 0), (2, 0)])
+            }
+            event => panic!("expected a paint, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn strokes_and_undos_never_overtake_paints() {
+        let (mut room, drawer, mut guesser) = drawing_room();
+
+        for draw in [paint(0, Color::Red), Draw::EndStroke, paint(1, Color::Red)] {
+            room.on_message(RoomMessage::Draw {
+                from: drawer.clone(),
+                draw,
+            });
+        }
+        room.on_message(RoomMessage::Draw {
+            from: drawer,
+            draw: Draw::Undo,
+        });
+
+        // the end of the stroke keeps its paints apart, the undo comes after both
+        for _ in 0..2 {
+            assert!(matches!(
+                received(&mut guesser),
+                GameEvent::Draw(Draw::Paint { .. })
+            ));
+        }
+        assert!(matches!(received(&mut guesser), GameEvent::CanvasPatch(_)));
+    }
+
+    #[test]
+    fn the_last_word_is_not_a_guess_while_choosing_the_next() {
+        let (mut room, drawer, mut guesser) = drawing_room();
+        let word = room.skribbl.as_ref().unwrap().word().clone();
+
+        room.reveal_word(false);
+        room.start_next_turn();
+
+        let game = room.skribbl.as_ref().unwrap();
+        assert!(matches!(
+            game.info.state.as_turn().map(|turn| &turn.phase),
+            Some(TurnPhase::ChoosingWord(_))
+        ));
+        // the last drawer guesses in this turn
+        assert!(!game.is_drawing(drawer.id()));
+
+        room.on_message(RoomMessage::Chat {
+            from: drawer,
+            msg: word.clone(),
+        });
+
+        let game = room.skribbl.as_ref().unwrap();
+        assert!(game
+            .info
+            .players
+            .iter()
+            .all(|pl| pl.secs_to_solve_turn == 0));
+
+        // the chat is passed on like any other message
+        let mut chat = None;
+        while let Some(msg) = guesser.try_recv() {
+            assert!(!matches!(
+                msg,
+                Event(RoomEvent::GameEvent(GameEvent::PlayerGuessed(_)))
+            ));
+            if let Event(RoomEvent::Chat(ChatMessage::User(_, msg))) = msg {
+                chat = Some(msg);
+            }
+        }
+        assert_eq!(chat, Some(word));
+    }
+
+    #[test]
+    fn leaders_are_only_handed_to_players_that_are_here() {
+        let (mut room, mut server, _queues) = room(3);
+
+        room.on_message(RoomMessage::Disconnect {
+            name: player(1),
+            session: 1,
+        });
+        room.on_message(RoomMessage::Leave {
+            name: player(0),
+            session: 0,
+        });
+        assert_eq!(room.leader, Some(player(2)));
+
+        // the last player left lost connection, nobody can lead
+        room.on_message(RoomMessage::Leave {
+            name: player(2),
+            session: 2,
+        });
+        let mut closing = false;
+        while let Some(msg) = server.try_recv() {
+            closing |= matches!(msg, ServerMessage::CloseRoom(key) if key == room.key);
+        }
+        assert!(closing);
+    }
+
+    #[test]
+    fn resuming_takes_over_a_session_not_known_to_be_lost() {
+        let (mut room, _, mut queues) = room(2);
+        let token = rand::thread_rng().gen();
+        room.resume_tokens.insert(player(1), token);
+
+        let mut resumed = EventQueue::default();
+        room.on_message(RoomMessage::Resume {
+            token,
+            session: 7,
+            inbox: resumed.sender().clone(),
+        });
+
+        assert!(matches!(resumed.try_recv(), Some(RoomJoined(..))));
+        assert!(matches!(queues[1].try_recv(), Some(KickFromRoom(_))));
+
+        // the old session finding out late does not touch the new one
+        room.on_message(RoomMessage::Disconnect {
+            name: player(1),
+            session: 1,
+        });
+        assert!(room.disconnected.is_empty());
+
+        room.on_message(RoomMessage::Disconnect {
+            name: player(1),
+            session: 7,
+        });
+        assert!(room.disconnected.contains_key(&player(1)));
+    }
+
+    #[test]
+    fn results_keep_the_profile_of_players_that_left() {
+        let (mut room, mut server, _queues) = room(1);
+        let queue = EventQueue::default();
+        room.on_message(RoomMessage::Join {
+            name: player(1),
+            inbox: queue.sender().clone(),
+            profile: Some(42),
+        });
+        room.on_message(RoomMessage::LeaderCommand {
+            from: player(0),
+            cmd: LeaderCommand::StartGame,
+        });
+        assert!(room.skribbl.is_some());
+
+        room.on_message(RoomMessage::Leave {
+            name: player(1),
+            session: 1,
+        });
+        room.end_game();
+
+        let mut results = None;
+        while let Some(msg) = server.try_recv() {
+            if let ServerMessage::GameFinished(game) = msg {
+                results = Some(game);
+            }
+        }
+        let results = results.expect("game results");
+        let profile_of = |id| {
+            results
+                .iter()
+                .find(|result| result.player == player(id))
+                .and_then(|result| result.profile)
+        };
+        assert_eq!(profile_of(0), None);
+        assert_eq!(profile_of(1), Some(42));
+        assert!(room.profiles.is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::data::{PlayerData, Scoring, UserId, Username};
+
+/// What a scoring rule knows about a finished turn
+#[derive(Debug, Clone)]
+pub struct TurnSummary {
+    /// number of seconds the turn lasted at most
+    pub draw_time: u64,
+
+    /// number of players that could have guessed the word
+    pub num_of_guessers: usize,
+
+    /// seconds each correct guess took, quickest first
+    pub solve_times: Vec<u64>,
+}
+
+impl TurnSummary {
+    pub fn num_of_solved(&self) -> usize { self.solve_times.len() }
+}
+
+/// A way of handing out points at the end of a turn
+pub trait ScoringRule: Send + Sync {
+    /// points for the `rank`th (starting at 0) player to guess the word, after `secs`
+    fn guesser_score(&self, turn: &TurnSummary, rank: usize, secs: u64) -> usize;
+
+    /// points for the drawing player
+    fn drawer_score(&self, turn: &TurnSummary) -> usize;
+}
+
+/// skribbl.io like scoring, quick and early guesses are worth more, the drawer
+/// scores for each player that guessed
+pub struct Classic;
+
+impl Classic {
+    const MAX_TIME_POINTS: u64 = 250;
+    const MAX_RANK_BONUS: usize = 100;
+    const RANK_BONUS_STEP: usize = 20;
+    const MIN_GUESS_POINTS: usize = 50;
+    const DRAWER_POINTS_PER_GUESS: usize = 200;
+}
+
+impl ScoringRule for Classic {
+    fn guesser_score(&self, turn: &TurnSummary, rank: usize, secs: u64) -> usize {
+        let time_points = time_left_share(turn.draw_time, secs, Self::MAX_TIME_POINTS);
+        let rank_bonus = Self::MAX_RANK_BONUS.saturating_sub(rank * Self::RANK_BONUS_STEP);
+
+        Self::MIN_GUESS_POINTS + time_points + rank_bonus
+    }
+
+    fn drawer_score(&self, turn: &TurnSummary) -> usize {
+        if turn.num_of_guessers == 0 {
+            return 0;
+        }
+
+        Self::DRAWER_POINTS_PER_GUESS * turn.num_of_solved() / turn.num_of_guessers
+    }
+}
+
+/// points go down linearly with the time it took to guess, the drawer gets the
+/// average of what the guessers could have made
+pub struct Linear;
+
+impl Linear {
+    const MAX_POINTS: u64 = 300;
+}
+
+impl ScoringRule for Linear {
+    fn guesser_score(&self, turn: &TurnSummary, _rank: usize, secs: u64) -> usize {
+        time_left_share(turn.draw_time, secs, Self::MAX_POINTS)
+    }
+
+    fn drawer_score(&self, turn: &TurnSummary) -> usize {
+        if turn.num_of_guessers == 0 {
+            return 0;
+        }
+
+        let total: usize = turn
+            .solve_times
+            .iter()
+            .enumerate()
+            .map(|(rank, secs)| self.guesser_score(turn, rank, *secs))
+            .sum();
+
+        total / turn.num_of_guessers
+    }
+}
+
+/// every correct guess is worth the same
+pub struct Flat;
+
+impl Flat {
+    const GUESS_POINTS: usize = 100;
+    const DRAWER_POINTS_PER_GUESS: usize = 50;
+}
+
+impl ScoringRule for Flat {
+    fn guesser_score(&self, _turn: &TurnSummary, _rank: usize, _secs: u64) -> usize {
+        Self::GUESS_POINTS
+    }
+
+    fn drawer_score(&self, turn: &TurnSummary) -> usize {
+        Self::DRAWER_POINTS_PER_GUESS * turn.num_of_solved()
+    }
+}
+
+pub fn rule(scoring: Scoring) -> Box<dyn ScoringRule> {
+    match scoring {
+        Scoring::Classic => Box::new(Classic),
+        Scoring::Linear => Box::new(Linear),
+        Scoring::Flat => Box::new(Flat),
+    }
+}
+
+/// `max_points` scaled by the share of `draw_time` that was left after `secs`
+fn time_left_share(draw_time: u64, secs: u64, max_points: u64) -> usize {
+    if draw_time == 0 {
+        return 0;
+    }
+
+    (max_points * draw_time.saturating_sub(secs) / draw_time) as usize
+}
+
+/// points every player gets for a turn, highest first. players that didn't guess
+/// the word, including teammates of the drawer, get nothing.
+pub fn score_turn(
+    rule: &dyn ScoringRule,
+    players: &[PlayerData],
+    who_is_drawing: UserId,
+    draw_time: u64,
+) -> Vec<(Username, usize)> {
+    let drawing_team = players
+        .iter()
+        .find(|pl| pl.name.id() == who_is_drawing)
+        .and_then(|pl| pl.team);
+
+    let is_guesser = |pl: &&PlayerData| {
+        pl.name.id() != who_is_drawing && (drawing_team.is_none() || pl.team != drawing_team)
+    };
+
+    let mut solved: Vec<&PlayerData> = players
+        .iter()
+        .filter(is_guesser)
+        .filter(|pl| pl.secs_to_solve_turn != 0)
+        .collect();
+    solved.sort_by_key(|pl| pl.secs_to_solve_turn);
+
+    let turn = TurnSummary {
+        draw_time,
+        num_of_guessers: players.iter().filter(is_guesser).count(),
+        solve_times: solved.iter().map(|pl| pl.secs_to_solve_turn).collect(),
+    };
+
+    let mut scores: Vec<(Username, usize)> = players
+        .iter()
+        .map(|pl| {
+            let score = if pl.name.id() == who_is_drawing {
+                rule.drawer_score(&turn)
+            } else {
+                solved
+                    .iter()
+                    .position(|solver| solver.name == pl.name)
+                    .map_or(0, |rank| {
+                        rule.guesser_score(&turn, rank, pl.secs_to_solve_turn)
+                    })
+            };
+
+            (pl.name.clone(), score)
+        })
+        .collect();
+
+    scores.sort_by(|(_, a), (_, b)| b.cmp(a));
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAW_TIME: u64 = 100;
+
+    fn player(id: UserId, secs_to_solve_turn: u64) -> PlayerData {
+        PlayerData {
+            secs_to_solve_turn,
+            ..Username::new(format!("player{}", id), id).into()
+        }
+    }
+
+    fn score_of(scores: &[(Username, usize)], id: UserId) -> usize {
+        scores
+            .iter()
+            .find(|(name, _)| name.id() == id)
+            .map(|(_, score)| *score)
+            .unwrap()
+    }
+
+    #[test]
+    fn non_guessers_score_nothing() {
+        let players = vec![player(0, 0), player(1, 0), player(2, 0)];
+
+        for scoring in [Scoring::Classic, Scoring::Linear, Scoring::Flat].iter() {
+            let scores = score_turn(rule(*scoring).as_ref(), &players, 0, DRAW_TIME);
+            assert!(scores.iter().all(|(_, score)| *score == 0), "{:?}", scoring);
+        }
+    }
+
+    #[test]
+    fn slow_guesses_do_not_underflow() {
+        let players = vec![player(0, 0), player(1, 90), player(2, 100), player(3, 250)];
+
+        for scoring in [Scoring::Classic, Scoring::Linear, Scoring::Flat].iter() {
+            let scores = score_turn(rule(*scoring).as_ref(), &players, 0, DRAW_TIME);
+            assert_eq!(scores.len(), players.len());
+        }
+    }
+
+    #[test]
+    fn classic_rewards_quick_and_early_guesses() {
+        let players = vec![player(0, 0), player(1, 10), player(2, 40), player(3, 0)];
+        let scores = score_turn(&Classic, &players, 0, DRAW_TIME);
+
+        // 50 + 250 * 90 / 100 + 100
+        assert_eq!(score_of(&scores, 1), 375);
+        // 50 + 250 * 60 / 100 + 80
+        assert_eq!(score_of(&scores, 2), 280);
+        assert_eq!(score_of(&scores, 3), 0);
+        // 2 of 3 guessers solved
+        assert_eq!(score_of(&scores, 0), 133);
+        assert_eq!(scores[0].0.id(), 1);
+    }
+
+    #[test]
+    fn linear_drawer_gets_average_of_guessers() {
+        let players = vec![player(0, 0), player(1, 50), player(2, 0)];
+        let scores = score_turn(&Linear, &players, 0, DRAW_TIME);
+
+        assert_eq!(score_of(&scores, 1), 150);
+        assert_eq!(score_of(&scores, 2), 0);
+        assert_eq!(score_of(&scores, 0), 75);
+    }
+
+    #[test]
+    fn flat_ignores_time() {
+        let players = vec![player(0, 0), player(1, 1), player(2, 99)];
+        let scores = score_turn(&Flat, &players, 0, DRAW_TIME);
+
+        assert_eq!(score_of(&scores, 1), 100);
+        assert_eq!(score_of(&scores, 2), 100);
+        assert_eq!(score_of(&scores, 0), 100);
+    }
+
+    #[test]
+    fn teammates_of_drawer_are_not_guessers() {
+        let mut players = vec![player(0, 0), player(1, 0), player(2, 20), player(3, 0)];
+        for (pl, team) in players.iter_mut().zip([0, 0, 1, 1].iter()) {
+            pl.team = Some(*team);
+        }
+
+        let scores = score_turn(&Classic, &players, 0, DRAW_TIME);
+
+        assert_eq!(score_of(&scores, 1), 0);
+        // 1 of 2 guessers solved
+        assert_eq!(score_of(&scores, 0), 100);
+    }
+}
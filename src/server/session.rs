@@ -1,9 +1,11 @@
 use crate::{
     data::{UserId, Username},
     events::{EventQueue, EventSender},
-    message::{self, InitialRoomState, RoomEvent, ToClient, ToServer},
+    message::{
+        self, Handshake, InitialRoomState, Leaderboard, ProfileKey, RoomEvent, ToClient, ToServer,
+    },
     server::Message as ServerMessage,
-    utils::{self, AbortableTask, MessageReader, MessageWriter},
+    utils::{self, AbortableTask, MessageReader, MessageWriter, Transport},
 };
 use futures_util::{SinkExt, StreamExt};
 use rand::Rng;
@@ -17,23 +19,26 @@ use super::room::RoomMessage;
 /// Disconnect client after this seconds of no heartbeat
 pub const TIMED_OUT_SECONDS: u64 = 5;
 
-type ClientMessageReader = MessageReader<ToServer>;
-type ClientMessageWriter = MessageWriter<ToClient>;
+type ClientMessageReader<S> = MessageReader<ToServer, S>;
+type ClientMessageWriter<S> = MessageWriter<ToClient, S>;
 
 pub type UserSessionInbox = EventSender<Message>;
 
 /// Chat server sends this messages to session
+#[allow(clippy::large_enum_variant)]
 pub enum Message {
     RoomEvent(RoomEvent),
     RoomJoined(EventSender<RoomMessage>, InitialRoomState),
     RoomNotFound,
     Kick(String),
+    KickFromRoom(String),
     RoomClosed,
+    Leaderboard(Option<Leaderboard>),
 }
 
 #[derive(Clone)]
 pub enum UserState {
-    // AwaitingVersionCheck
+    AwaitingVersionCheck,
     Idle,
     InQueue {
         username: Username,
@@ -46,8 +51,8 @@ pub enum UserState {
     Stopped,
 }
 
-/// `UserSession` actor is responsible for TCP peer communications.
-pub struct UserSession {
+/// `UserSession` actor is responsible for communicating with a peer over its transport.
+pub struct UserSession<S> {
     /// unique session id
     id: UserId,
     /// socket address
@@ -59,7 +64,7 @@ pub struct UserSession {
     /// this is sender for server event queue
     server: EventSender<ServerMessage>,
     /// Framed sockets
-    framed: (ClientMessageReader, ClientMessageWriter),
+    framed: (ClientMessageReader<S>, ClientMessageWriter<S>),
     /// client must send a message at least once every 5 seconds
     last_hb: Instant,
 }
@@ -67,16 +72,26 @@ pub struct UserSession {
 pub struct User {
     pub inbox: UserSessionInbox,
     pub thread_handle: AbortableTask<()>,
+    /// profile the client plays as, if it has one
+    pub profile: Option<ProfileKey>,
+}
+
+/// tell a client its connection is refused before a session is created for it
+pub async fn refuse<S: Transport>(mut writer: ClientMessageWriter<S>, reason: &str) {
+    if let Err(err) = writer.send(ToClient::Disconnect(reason.to_owned())).await {
+        log::error!("{:?}", err);
+    }
+    let _ = writer.close().await;
 }
 
-impl UserSession {
+impl<S: Transport> UserSession<S> {
     const NAMES: [&'static str; 4] = ["alice", "bob", "dafny", "spice"];
 
     pub fn create_user(
         id: UserId,
         peer_addr: SocketAddr,
         server: EventSender<super::Message>,
-        client_msg_stream: (ClientMessageReader, ClientMessageWriter),
+        client_msg_stream: (ClientMessageReader<S>, ClientMessageWriter<S>),
     ) -> User {
         let session = Self {
             id,
@@ -84,13 +99,14 @@ impl UserSession {
             server,
             framed: client_msg_stream,
             event_queue: EventQueue::default(),
-            state: UserState::Idle,
+            state: UserState::AwaitingVersionCheck,
             last_hb: Instant::now(),
         };
 
         User {
             inbox: session.sender().clone(),
             thread_handle: utils::dispatch_abortable_task(session.run()),
+            profile: None,
         }
     }
 
@@ -101,14 +117,14 @@ impl UserSession {
 
     pub fn sender(&self) -> &UserSessionInbox { self.event_queue.sender() }
 
-    fn writer(&mut self) -> &mut ClientMessageWriter { &mut self.framed.1 }
+    fn writer(&mut self) -> &mut ClientMessageWriter<S> { &mut self.framed.1 }
 
     /// Forward server message to this client
     async fn send(&mut self, msg: ToClient) {
         log::trace!("({}): writing message <> {:?}", self.peer_addr, msg);
         if let Err(err) = self.writer().send(msg).await {
             log::error!("{:?}", err);
-            self.stop();
+            self.drop_connection();
         }
     }
 
@@ -117,10 +133,32 @@ impl UserSession {
         if let UserState::InRoom { room, username, .. } = &self.state {
             room.send_with_urgency(RoomMessage::Leave {
                 name: username.clone(),
+                session: self.id,
             });
         }
     }
 
+    /// leave room on request of the client
+    async fn on_leave_room(&mut self) {
+        if let UserState::InRoom { .. } = &self.state {
+            self.leave_room();
+            self.state = UserState::Idle;
+            self.send(ToClient::LeaveRoom(None)).await;
+        }
+    }
+
+    /// connection to the client was lost, let the room keep our place for a while
+    fn drop_connection(&mut self) {
+        if let UserState::InRoom { room, username, .. } = &self.state {
+            room.send_with_urgency(RoomMessage::Disconnect {
+                name: username.clone(),
+                session: self.id,
+            });
+        }
+
+        self.state = UserState::Stopped;
+    }
+
     fn stop(&mut self) {
         self.leave_room();
 
@@ -134,22 +172,41 @@ impl UserSession {
         self.stop()
     }
 
+    /// check the client is a compatible Termibbl client
+    async fn on_handshake(&mut self, handshake: Handshake) {
+        if !matches!(self.state, UserState::AwaitingVersionCheck) {
+            return self
+                .kick("You are being naughty, got a unexpected handshake.".to_owned())
+                .await;
+        }
+
+        // always answer with our handshake so the client knows what it is talking to
+        self.send(ToClient::Handshake(Handshake::default())).await;
+
+        if let Some(reason) = handshake.incompatibility() {
+            log::info!("({}): rejected client <> {}", self.peer_addr, reason);
+            self.kick(reason).await
+        } else {
+            self.state = UserState::Idle;
+        }
+    }
+
     async fn on_room_joined(
         &mut self,
         room: EventSender<RoomMessage>,
         mut initial_room_state: InitialRoomState,
     ) {
-        if let UserState::InQueue { username } = &self.state {
+        if let UserState::InQueue { .. } = &self.state {
+            // room decides the username, a resumed session gets its old one back
+            let username = &initial_room_state.username;
+
             if let Some(ref mut game) = initial_room_state.game {
-                let this_player_index = game
-                    .players
-                    .iter()
-                    .enumerate()
-                    .find(|(_, p)| &p.name == username)
-                    .expect("room sent empty player list")
-                    .0;
-
-                game.players.swap(0, this_player_index);
+                // players joining a running game are not in the player list yet
+                if let Some(this_player_index) =
+                    game.players.iter().position(|p| &p.name == username)
+                {
+                    game.players.swap(0, this_player_index);
+                }
             }
 
             let key = initial_room_state.room.key.clone();
@@ -167,13 +224,18 @@ impl UserSession {
         }
     }
 
-    /// Handle messages from the tcp stream of the client (Client -> Server)
+    /// Handle messages from the connection of the client (Client -> Server)
     async fn on_user_msg(&mut self, msg: ToServer) {
         log::debug!("({}): processing message <> {:?}", self.peer_addr, msg);
 
         match &self.state {
-            UserState::Idle => {
-                if let ToServer::RequestRoom(maybe_name, req) = msg {
+            UserState::AwaitingVersionCheck => {
+                self.kick("Expected a handshake, is this a Termibbl client?".to_owned())
+                    .await
+            }
+
+            UserState::Idle => match msg {
+                ToServer::RequestRoom(maybe_name, req) => {
                     let username =
                         Username::new(maybe_name.unwrap_or_else(Self::generate_name), self.id);
 
@@ -185,10 +247,23 @@ impl UserSession {
                         from: username,
                         req,
                     });
-                } else {
+                }
+
+                ToServer::Profile(key) => self
+                    .server
+                    .send(ServerMessage::Profile { id: self.id, key }),
+
+                ToServer::Leaderboard(period) => {
+                    self.server.send(ServerMessage::LeaderboardRequest {
+                        id: self.id,
+                        period,
+                    })
+                }
+
+                _ => {
                     // TODO: recieved weird messaage from client, is client laggin? maybe disconnect
                 }
-            }
+            },
 
             UserState::InRoom { room, username, .. } => {
                 match msg {
@@ -202,6 +277,16 @@ impl UserSession {
                         draw,
                     }),
 
+                    ToServer::ChooseWord(word) => room.send(RoomMessage::ChooseWord {
+                        from: username.clone(),
+                        word,
+                    }),
+
+                    ToServer::Leader(cmd) => room.send(RoomMessage::LeaderCommand {
+                        from: username.clone(),
+                        cmd,
+                    }),
+
                     ToServer::RequestRoom(_, _) => {
                         self.kick("You are not allowed to join multiple game rooms.".to_owned())
                             .await
@@ -275,6 +360,13 @@ impl UserSession {
                             self.state = UserState::Idle;
                             self.send(ToClient::LeaveRoom(None)).await;
                         }
+                        Message::KickFromRoom(reason) => {
+                            self.state = UserState::Idle;
+                            self.send(ToClient::LeaveRoom(Some(reason))).await;
+                        }
+                        Message::Leaderboard(leaderboard) => {
+                            self.send(ToClient::Leaderboard(leaderboard)).await
+                        }
                     }
                 },
 
@@ -286,13 +378,20 @@ impl UserSession {
                                     self.last_hb = Instant::now();
                                     hb_check.sender().send_with_delay(CheckHeartBeat, timeout_duration);
                                 },
-                                ToServer::LeaveRoom => self.leave_room(),
+                                ToServer::Handshake(handshake) => self.on_handshake(handshake).await,
+                                ToServer::LeaveRoom => self.on_leave_room().await,
                                 ToServer::Disconnect => self.stop(),
                                 _ => self.on_user_msg(msg).await,
                             };
                          }
                          Err(err) => {
                             log::error!("decode err {:?}", err);
+
+                            if let UserState::AwaitingVersionCheck = self.state {
+                                let reason = "Not a compatible Termibbl client".to_owned();
+                                let _ = self.writer().send(ToClient::Disconnect(reason)).await;
+                            }
+
                             break;
                          }
                      }
@@ -301,6 +400,9 @@ impl UserSession {
             }
         }
 
+        // connection closed without the client leaving
+        self.drop_connection();
+
         // notify server
         self.server.send(ServerMessage::Disconnect(self.id));
 
@@ -1,22 +1,89 @@
-use std::{cmp::min, sync::Arc};
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use rand::{
     prelude::{IteratorRandom, SliceRandom, StdRng},
-    SeedableRng,
+    Rng, SeedableRng,
 };
 
 use crate::{
     data::{
-        GameInfo, GameOpts, GameState, PlayerData, Turn, TurnPhase, UserId, Username, WordHint,
+        GameInfo, GameMode, GameOpts, GameState, PlayerData, TeamId, Turn, TurnPhase, UserId,
+        Username, WordHint,
     },
+    message::{CanvasPatch, Draw, ProfileKey},
     utils,
 };
 
+use super::{
+    profiles::GameResult,
+    scoring::{self, ScoringRule},
+    word_pack::{Difficulty, WordPack},
+};
+
 pub const NUM_OF_WORDS_PER_TURN: usize = 3;
 pub const DEFAULT_DRAW_TIME: u64 = 120;
 pub const DEFAULT_NUM_OF_ROUNDS: usize = 3;
 pub const CHOOSE_WORDS_TIME: u64 = 10; // num of seconds for players to choose word before timeout
 pub const REVEAL_PHASE_SECS: u64 = 3; // num of seconds in reveal word phase
+pub const ROUND_START_SECS: u64 = 5; // num of seconds between rounds
+pub const FINISH_SECS: u64 = 5; // num of seconds results are shown before the game ends
+
+/// Ordered log of the strokes drawn in the current turn, used to undo and redo them
+#[derive(Default)]
+struct StrokeLog {
+    /// strokes on the canvas, each made of one or more draws
+    strokes: Vec<Vec<Draw>>,
+
+    /// undone strokes, the last one is redone first
+    undone: Vec<Vec<Draw>>,
+
+    /// whether the last stroke is a freehand stroke still being drawn
+    open: bool,
+}
+
+impl StrokeLog {
+    fn record(&mut self, draw: Draw) {
+        match draw {
+            Draw::EndStroke => self.open = false,
+            Draw::Undo | Draw::Redo => {}
+            Draw::Paint { .. } | Draw::Erase(_) => {
+                match self.strokes.last_mut() {
+                    Some(stroke) if self.open => stroke.push(draw),
+                    _ => self.strokes.push(vec![draw]),
+                }
+                self.open = true;
+                self.undone.clear();
+            }
+            Draw::Clear | Draw::Fill { .. } | Draw::Shape { .. } => {
+                self.strokes.push(vec![draw]);
+                self.open = false;
+                self.undone.clear();
+            }
+        }
+    }
+
+    fn undo(&mut self) -> bool {
+        self.open = false;
+        self.strokes
+            .pop()
+            .map(|stroke| self.undone.push(stroke))
+            .is_some()
+    }
+
+    fn redo(&mut self) -> bool {
+        self.open = false;
+        self.undone
+            .pop()
+            .map(|stroke| self.strokes.push(stroke))
+            .is_some()
+    }
+
+    fn draws(&self) -> impl Iterator<Item = &Draw> { self.strokes.iter().flatten() }
+}
 
 /// This struct is used to hold and collect words that can be used for
 /// guessing in this game
@@ -24,51 +91,142 @@ struct WordProducer {
     /// random generator for this producer
     rng: StdRng,
 
-    /// Vec of words that could be used in this producer
+    /// Vec of words that could be used in this producer, shuffled so they can be popped
     words: Vec<String>,
 
-    /// vec of additional words
-    shared_words: Arc<Vec<String>>,
+    /// word packs shared by the server
+    shared_words: Arc<Vec<WordPack>>,
+
+    /// shuffled (pack, word) indices into `shared_words` that have not been offered yet
+    shared_words_left: HashMap<Difficulty, Vec<(usize, usize)>>,
+
+    /// words that have already been offered in this game
+    offered: HashSet<String>,
 
     num_of_words: usize,
 
     use_shared_words: bool,
+
+    /// chance in percent of taking a word from `words` over `shared_words`
+    custom_words_ratio: u8,
 }
 
 impl WordProducer {
-    // const WORDS_TO_SHARED_WEIGHT: (usize,usize) = (2, 1); // 66%, 33%
-
     fn new(
         words: Vec<String>,
-        shared_words: Arc<Vec<String>>,
+        shared_words: Arc<Vec<WordPack>>,
+        pack_names: &[String],
         use_shared_words: bool,
+        custom_words_ratio: u8,
         num_of_words: usize,
     ) -> Self {
+        let mut rng: StdRng = SeedableRng::from_entropy();
+
+        let mut words = words;
+        words.shuffle(&mut rng);
+
+        let mut shared_words_left = HashMap::new();
+        if use_shared_words {
+            let packs = shared_words
+                .iter()
+                .enumerate()
+                .filter(|(_, pack)| pack_names.is_empty() || pack_names.contains(&pack.name));
+
+            for (pack_idx, pack) in packs {
+                for &difficulty in Difficulty::ALL.iter() {
+                    let num_of_words = pack.words.get(difficulty).len();
+                    shared_words_left
+                        .entry(difficulty)
+                        .or_insert_with(Vec::new)
+                        .extend((0..num_of_words).map(|word_idx| (pack_idx, word_idx)));
+                }
+            }
+
+            for indices in shared_words_left.values_mut() {
+                indices.shuffle(&mut rng);
+            }
+        }
+
         Self {
             shared_words,
+            shared_words_left,
             words,
+            offered: HashSet::new(),
             use_shared_words,
-            rng: SeedableRng::from_entropy(),
+            custom_words_ratio,
+            rng,
             num_of_words,
         }
     }
 
     fn rng(&mut self) -> &mut StdRng { &mut self.rng }
 
-    fn next_custom(&mut self) -> Option<String> { None }
+    /// take a word from `words` that has not been offered yet
+    fn next_custom(&mut self) -> Option<String> {
+        while let Some(word) = self.words.pop() {
+            if self.offered.insert(word.clone()) {
+                return Some(word);
+            }
+        }
+
+        None
+    }
+
+    /// take a word of the given difficulty from `shared_words` that has not been offered yet
+    fn next_shared(&mut self, difficulty: Difficulty) -> Option<String> {
+        let indices = self.shared_words_left.get_mut(&difficulty)?;
+
+        while let Some((pack_idx, word_idx)) = indices.pop() {
+            let word = &self.shared_words[pack_idx].words.get(difficulty)[word_idx];
+            if self.offered.insert(word.clone()) {
+                return Some(word.clone());
+            }
+        }
+
+        None
+    }
+
+    /// take a shared word, preferring the given difficulty
+    fn next_shared_any(&mut self, difficulty: Difficulty) -> Option<String> {
+        self.next_shared(difficulty).or_else(|| {
+            Difficulty::ALL
+                .iter()
+                .filter(|d| **d != difficulty)
+                .find_map(|d| self.next_shared(*d))
+        })
+    }
+
+    fn next_word(&mut self, difficulty: Difficulty) -> Option<String> {
+        let prefer_custom = self.rng.gen_range(0, 100) < self.custom_words_ratio;
+
+        if prefer_custom {
+            self.next_custom()
+                .or_else(|| self.next_shared_any(difficulty))
+        } else {
+            self.next_shared_any(difficulty)
+                .or_else(|| self.next_custom())
+        }
+    }
 }
 
 impl Iterator for WordProducer {
     type Item = Vec<String>;
 
-    // TODO: use weights to choose words. just return shared words for now
+    /// returns the next words to choose from, one per difficulty if possible,
+    /// or `None` once the pool runs out
     fn next(&mut self) -> Option<Self::Item> {
-        Some(
-            self.shared_words
-                .choose_multiple(&mut self.rng, self.num_of_words)
-                .cloned()
-                .collect(),
-        )
+        let words: Vec<String> = Difficulty::ALL
+            .iter()
+            .cycle()
+            .take(self.num_of_words)
+            .filter_map(|difficulty| self.next_word(*difficulty))
+            .collect();
+
+        if words.is_empty() {
+            None
+        } else {
+            Some(words)
+        }
     }
 }
 
@@ -86,20 +244,30 @@ pub struct SkribblState {
     /// current word to guess
     current_word: String,
 
+    /// words the drawing player can choose from in the current turn
+    word_choices: Vec<String>,
+
     /// number of seconds players have to draw
     draw_time: usize,
+
+    /// how points are handed out at the end of each turn
+    scoring: Box<dyn ScoringRule>,
+
+    /// strokes drawn on the canvas in the current turn
+    strokes: StrokeLog,
+
+    /// number of words each player guessed
+    words_guessed: HashMap<UserId, usize>,
 }
 
 impl SkribblState {
-    pub fn new(
-        opts: GameOpts,
-        users: Vec<Username>,
-        shared_server_words: Arc<Vec<String>>,
-    ) -> Self {
+    pub fn new(opts: GameOpts, users: Vec<Username>, word_packs: Arc<Vec<WordPack>>) -> Self {
         let words = WordProducer::new(
             opts.custom_words,
-            shared_server_words,
+            word_packs,
+            &opts.word_packs,
             !opts.only_custom_words,
+            opts.custom_words_ratio,
             NUM_OF_WORDS_PER_TURN,
         );
 
@@ -109,7 +277,7 @@ impl SkribblState {
             round_num: 0,
             next_phase_timestamp: 0,
             num_of_rounds: opts.number_of_rounds,
-            players: users.into_iter().map(|username| username.into()).collect(),
+            players: assign_teams(opts.game_mode, users),
             canvas: Default::default(),
         };
 
@@ -117,8 +285,12 @@ impl SkribblState {
             info,
             players_left_in_round: Vec::new(),
             draw_time: opts.draw_time,
+            scoring: scoring::rule(opts.scoring),
             words,
             current_word: String::new(),
+            word_choices: Vec::new(),
+            strokes: StrokeLog::default(),
+            words_guessed: HashMap::new(),
         };
 
         new.start_round();
@@ -128,11 +300,13 @@ impl SkribblState {
 
     pub fn word(&self) -> &String { &self.current_word }
 
+    pub fn word_choices(&self) -> &[String] { &self.word_choices }
+
     pub fn has_round_ended(&self) -> bool { self.players_left_in_round.is_empty() }
 
     pub fn end(&mut self) {
         self.info.state = GameState::Finish;
-        self.info.next_phase_timestamp = utils::get_time_now() + 5;
+        self.info.next_phase_timestamp = utils::get_time_now() + FINISH_SECS;
     }
 
     pub fn start_round(&mut self) {
@@ -142,74 +316,113 @@ impl SkribblState {
             self.end();
         } else {
             *round_num += 1;
-            self.players_left_in_round = self.info.players.iter().map(|pl| pl.name.id()).collect();
-            self.info.state = GameState::RoundStart(*round_num);
-            self.info.next_phase_timestamp = utils::get_time_now() + 5;
+            let round_num = *round_num;
+            self.players_left_in_round = self.drawers_in_round(round_num);
+            self.info.state = GameState::RoundStart(round_num);
+            self.info.next_phase_timestamp = utils::get_time_now() + ROUND_START_SECS;
+        }
+    }
+
+    /// players that draw in the given round, in team games one member of each team draws,
+    /// taking turns between rounds
+    fn drawers_in_round(&self, round_num: usize) -> Vec<UserId> {
+        let players = &self.info.players;
+
+        if !self.info.is_team_game() {
+            return players.iter().map(|pl| pl.name.id()).collect();
         }
+
+        let mut teams: Vec<TeamId> = players.iter().filter_map(|pl| pl.team).collect();
+        teams.sort_unstable();
+        teams.dedup();
+
+        teams
+            .into_iter()
+            .filter_map(|team| {
+                let members: Vec<UserId> = players
+                    .iter()
+                    .filter(|pl| pl.team == Some(team))
+                    .map(|pl| pl.name.id())
+                    .collect();
+
+                members.get((round_num - 1) % members.len()).copied()
+            })
+            .collect()
     }
 
-    pub fn start_next_turn(&mut self) {
+    /// start the turn of the next player, returns false if the game ended because
+    /// there are no words left to draw
+    pub fn start_next_turn(&mut self) -> bool {
         if self.players_left_in_round.is_empty() {
             self.start_round();
-            return;
+            return true;
         }
 
-        // set next turn
+        self.word_choices = match self.words.next() {
+            Some(words) => words,
+            None => {
+                self.end();
+                return false;
+            }
+        };
+
+        // set next turn, choices are kept out of the shared state so only the drawer sees them
         self.info.state = GameState::Playing(Turn {
             who_is_drawing: self.players_left_in_round.pop().unwrap(),
-            phase: TurnPhase::ChoosingWord(self.words.next().unwrap()),
+            phase: TurnPhase::ChoosingWord(Vec::new()),
         });
         self.info.next_phase_timestamp = utils::get_time_now() + CHOOSE_WORDS_TIME;
-        self.info.next_phase_timestamp = utils::get_time_now(); // skip choosing word for now
+        true
     }
 
+    /// set the word to draw, picks a random choice if given word is none or not one of the choices
     pub fn choose_draw_word(&mut self, word: Option<String>) {
         if let GameState::Playing(turn) = &mut self.info.state {
             let rng = self.words.rng();
-            let word = if let TurnPhase::ChoosingWord(ref choices) = turn.phase {
-                word.or_else(|| choices.choose(rng).cloned()).unwrap()
+            let word = if let TurnPhase::ChoosingWord(_) = turn.phase {
+                let choices = &self.word_choices;
+                word.filter(|word| choices.contains(word))
+                    .or_else(|| choices.choose(rng).cloned())
             } else {
                 return;
             };
 
+            let word = match word {
+                Some(word) => word,
+                None => return self.end(), // nothing to choose from
+            };
+
             // set next phase
             turn.phase = TurnPhase::Drawing(word.as_str().into());
 
             self.info.next_phase_timestamp = utils::get_time_now() + (self.draw_time as u64);
             self.current_word = word;
+            self.word_choices.clear();
         }
     }
 
     pub fn end_turn(&mut self, timed_out: bool) {
-        let draw_time = self.draw_time;
         let game_info = &mut self.info;
-        // let num_of_players = game_info.players.len();
-        let remaining_secs = game_info.remaining_secs_in_phase();
 
         if let GameState::Playing(turn) = &mut game_info.state {
-            let who_is_drawing = turn.who_is_drawing;
-            // sort by who solved quicker
-            game_info
-                .players
-                .sort_by(|a, b| a.secs_to_solve_turn.cmp(&b.secs_to_solve_turn));
-
-            // scoring algo needs more work
-            let scores: Vec<(Username, usize)> = game_info
-                .players
-                .iter_mut()
-                .enumerate()
-                .map(|(idx, player)| {
-                    let score = if player.name.id() == who_is_drawing {
-                        (remaining_secs * (200 / draw_time) as u64) as usize + (5 * idx)
-                    } else {
-                        300 - (idx * player.secs_to_solve_turn as usize)
-                    };
-
-                    player.secs_to_solve_turn = 0;
+            let scores = scoring::score_turn(
+                self.scoring.as_ref(),
+                &game_info.players,
+                turn.who_is_drawing,
+                self.draw_time as u64,
+            );
+
+            for player in game_info.players.iter_mut() {
+                if let Some((_, score)) = scores.iter().find(|(name, _)| name == &player.name) {
                     player.score += score;
-                    (player.name.clone(), score)
-                })
-                .collect();
+                }
+
+                if player.secs_to_solve_turn > 0 {
+                    *self.words_guessed.entry(player.name.id()).or_default() += 1;
+                }
+
+                player.secs_to_solve_turn = 0;
+            }
 
             // set next phase
             turn.phase = TurnPhase::RevealWord {
@@ -217,19 +430,106 @@ impl SkribblState {
                 scores,
                 timed_out,
             };
-            self.info.next_phase_timestamp = utils::get_time_now() + REVEAL_PHASE_SECS;
+            game_info.next_phase_timestamp = utils::get_time_now() + REVEAL_PHASE_SECS;
         }
     }
 
-    /// try guess for a player by username, returns distance of guess
+    /// how every player did, the players with the best score, or in the best team, won. Results
+    /// are linked to the profiles players joined with
+    pub fn results(&self, profiles: &HashMap<Username, ProfileKey>) -> Vec<GameResult> {
+        let info = &self.info;
+        let team_scores = info.team_scores();
+        let ranked_score = |player: &PlayerData| match player.team {
+            Some(team) => team_scores
+                .iter()
+                .find(|(t, _)| *t == team)
+                .map_or(0, |(_, score)| *score),
+            None => player.score,
+        };
+        let best_score = info.players.iter().map(ranked_score).max().unwrap_or(0);
+
+        info.players
+            .iter()
+            .map(|player| GameResult {
+                player: player.name.clone(),
+                profile: profiles.get(&player.name).copied(),
+                score: player.score,
+                won: best_score > 0 && ranked_score(player) == best_score,
+                words_guessed: self
+                    .words_guessed
+                    .get(&player.name.id())
+                    .copied()
+                    .unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// start over with an empty canvas and stroke log
+    pub fn clear_canvas(&mut self) {
+        self.info.canvas.clear();
+        self.strokes = StrokeLog::default();
+    }
+
+    /// apply a draw of the drawing player to the canvas, keeping it in the stroke log
+    pub fn draw(&mut self, draw: Draw) {
+        draw.apply(&mut self.info.canvas, self.info.dimensions);
+        self.strokes.record(draw);
+    }
+
+    /// take back the last stroke, returns the resulting change to the canvas
+    pub fn undo(&mut self) -> Option<CanvasPatch> {
+        if self.strokes.undo() {
+            Some(self.rebuild_canvas())
+        } else {
+            None
+        }
+    }
+
+    /// put back the last undone stroke, returns the resulting change to the canvas
+    pub fn redo(&mut self) -> Option<CanvasPatch> {
+        if self.strokes.redo() {
+            Some(self.rebuild_canvas())
+        } else {
+            None
+        }
+    }
+
+    /// redraw the canvas from the stroke log
+    fn rebuild_canvas(&mut self) -> CanvasPatch {
+        let mut canvas = HashMap::new();
+        for draw in self.strokes.draws() {
+            draw.apply(&mut canvas, self.info.dimensions);
+        }
+
+        let patch = CanvasPatch::between(&self.info.canvas, &canvas);
+        self.info.canvas = canvas;
+        patch
+    }
+
+    /// whether the word of the current turn is being drawn, the only time it can be guessed
+    pub fn is_guessing_phase(&self) -> bool {
+        matches!(
+            self.info.state.as_turn().map(|turn| &turn.phase),
+            Some(TurnPhase::Drawing(_))
+        )
+    }
+
+    /// try guess for a player by username, returns distance of guess. Outside of the drawing
+    /// phase the last word is still known, so nothing counts as a guess then
     pub fn do_guess(&mut self, player_name: &Username, guess: &str) -> usize {
+        if !self.is_guessing_phase() {
+            return 2;
+        }
+
         let remaining_secs = self.info.remaining_secs_in_phase();
         let draw_time = self.draw_time;
         let dist = levenshtein_distance(guess, &self.current_word);
 
         if let Some(player) = self.get_player_mut(player_name) {
             if dist == 0 {
-                player.secs_to_solve_turn = draw_time as u64 - remaining_secs;
+                // 0 means not solved yet, so count a guess in the first second as 1
+                player.secs_to_solve_turn =
+                    (draw_time as u64).saturating_sub(remaining_secs).max(1);
             }
 
             dist
@@ -256,7 +556,14 @@ impl SkribblState {
     }
 
     pub fn can_player_guess(&self, pl: &PlayerData) -> bool {
-        !(pl.secs_to_solve_turn != 0 || self.is_drawing(pl.name.id()))
+        let id = pl.name.id();
+        let is_drawing_team = self
+            .info
+            .state
+            .as_turn()
+            .is_some_and(|turn| self.info.are_teammates(turn.who_is_drawing, id));
+
+        !(pl.secs_to_solve_turn != 0 || self.is_drawing(id) || is_drawing_team)
     }
 
     pub fn is_drawing(&self, id: UserId) -> bool {
@@ -271,14 +578,14 @@ impl SkribblState {
     /// returns the index and character hinted if any
     pub fn reveal_random_char(&mut self) -> Option<(usize, char)> {
         let remaining_time = self.info.remaining_secs_in_phase();
-        let num_of_chars_to_reveal = self.current_word.len() - 1;
+        let num_of_chars_to_reveal = self.current_word.chars().count().saturating_sub(1);
 
         if let Some(WordHint::Hint { hints, .. }) = &mut self.info.state.as_turn_drawing_mut() {
             let should_reveal_char = {
-                if num_of_chars_to_reveal <= 1 {
+                if num_of_chars_to_reveal <= 1 || hints.len() >= num_of_chars_to_reveal {
                     false
                 } else {
-                    let char_reveal_interval = self.draw_time / num_of_chars_to_reveal;
+                    let char_reveal_interval = (self.draw_time / num_of_chars_to_reveal).max(1);
 
                     remaining_time as usize / char_reveal_interval
                         <= num_of_chars_to_reveal - hints.len()
@@ -286,17 +593,17 @@ impl SkribblState {
             };
 
             if should_reveal_char {
-                let (idx, ch) = self
+                let hint = self
                     .current_word
                     .chars()
                     .enumerate()
-                    .filter(|(idx, _)| !hints.contains_key(&idx))
-                    .choose(self.words.rng())
-                    .unwrap();
-
-                hints.insert(idx, ch);
+                    .filter(|(idx, _)| !hints.contains_key(idx))
+                    .choose(self.words.rng());
 
-                return Some((idx, ch));
+                if let Some((idx, ch)) = hint {
+                    hints.insert(idx, ch);
+                    return Some((idx, ch));
+                }
             }
         }
 
@@ -304,6 +611,23 @@ impl SkribblState {
     }
 }
 
+/// create player data for the users, split into balanced teams in a team game
+fn assign_teams(mode: GameMode, users: Vec<Username>) -> Vec<PlayerData> {
+    let mut players: Vec<PlayerData> = users.into_iter().map(PlayerData::from).collect();
+
+    if let GameMode::Teams(team_size) = mode {
+        // always at least two teams, or nobody would be left to guess
+        let num_of_teams = players.len().div_ceil(team_size).max(2);
+
+        players.shuffle(&mut rand::thread_rng());
+        for (idx, player) in players.iter_mut().enumerate() {
+            player.team = Some(idx % num_of_teams);
+        }
+    }
+
+    players
+}
+
 fn levenshtein_distance(a: &str, b: &str) -> usize {
     let w1 = a.chars().collect::<Vec<_>>();
     let w2 = b.chars().collect::<Vec<_>>();
@@ -333,3 +657,92 @@ fn levenshtein_distance(a: &str, b: &str) -> usize {
     }
     matrix[b_len - 1][a_len - 1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Scoring;
+
+    fn users(count: UserId) -> Vec<Username> {
+        (0..count)
+            .map(|id| Username::new(format!("player{}", id), id))
+            .collect()
+    }
+
+    fn team_sizes(players: &[PlayerData]) -> Vec<usize> {
+        let mut sizes = HashMap::<TeamId, usize>::new();
+        for player in players {
+            *sizes.entry(player.team.unwrap()).or_default() += 1;
+        }
+
+        let mut sizes: Vec<usize> = sizes.into_values().collect();
+        sizes.sort_unstable();
+        sizes
+    }
+
+    fn team_game(players: UserId, team_size: usize) -> SkribblState {
+        let opts = GameOpts {
+            dimensions: (120, 90),
+            number_of_rounds: 3,
+            draw_time: 80,
+            custom_words: Vec::new(),
+            only_custom_words: false,
+            custom_words_ratio: 0,
+            word_packs: Vec::new(),
+            game_mode: GameMode::Teams(team_size),
+            scoring: Scoring::Classic,
+        };
+        let packs = vec![WordPack::from_word_list("default", "cat\ndog\ntree\nhouse")];
+
+        SkribblState::new(opts, users(players), Arc::new(packs))
+    }
+
+    #[test]
+    fn teams_are_filled_up_to_their_size() {
+        assert_eq!(
+            team_sizes(&assign_teams(GameMode::Teams(2), users(5))),
+            vec![1, 2, 2]
+        );
+        assert_eq!(
+            team_sizes(&assign_teams(GameMode::Teams(3), users(6))),
+            vec![3, 3]
+        );
+
+        let free_for_all = assign_teams(GameMode::FreeForAll, users(4));
+        assert!(free_for_all.iter().all(|player| player.team.is_none()));
+    }
+
+    #[test]
+    fn there_are_always_two_teams() {
+        assert_eq!(
+            team_sizes(&assign_teams(GameMode::Teams(2), users(2))),
+            vec![1, 1]
+        );
+        assert_eq!(
+            team_sizes(&assign_teams(GameMode::Teams(5), users(3))),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn one_player_per_team_draws_each_round() {
+        let game = team_game(4, 2);
+        let team_of = |id: UserId| game.info.get_player(id).unwrap().team;
+
+        let first_round = game.drawers_in_round(1);
+        let second_round = game.drawers_in_round(2);
+        assert_eq!(game.players_left_in_round, first_round);
+
+        for drawers in [&first_round, &second_round].iter() {
+            assert_eq!(drawers.len(), 2);
+            assert_ne!(team_of(drawers[0]), team_of(drawers[1]));
+        }
+
+        // teammates take turns drawing
+        for (first, second) in first_round.iter().zip(second_round.iter()) {
+            assert_ne!(first, second);
+            assert_eq!(team_of(*first), team_of(*second));
+        }
+        assert_eq!(game.drawers_in_round(3), first_round);
+    }
+}
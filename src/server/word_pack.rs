@@ -0,0 +1,190 @@
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::data::WordPackInfo;
+
+/// name of the pack made of the plain word list the server was started with
+pub const DEFAULT_PACK_NAME: &str = "default";
+
+/// how hard a word is to draw or guess
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    /// guess the difficulty of a word that has none given by its length, longer words take
+    /// longer to draw and have more letters to get wrong
+    pub fn of_word(word: &str) -> Self {
+        match word.chars().count() {
+            0..=5 => Difficulty::Easy,
+            6..=8 => Difficulty::Medium,
+            _ => Difficulty::Hard,
+        }
+    }
+}
+
+/// words of a pack grouped by difficulty
+#[derive(Debug, Default, Deserialize)]
+pub struct PackWords {
+    #[serde(default)]
+    pub easy: Vec<String>,
+    #[serde(default)]
+    pub medium: Vec<String>,
+    #[serde(default)]
+    pub hard: Vec<String>,
+}
+
+impl PackWords {
+    pub fn get(&self, difficulty: Difficulty) -> &[String] {
+        match difficulty {
+            Difficulty::Easy => &self.easy,
+            Difficulty::Medium => &self.medium,
+            Difficulty::Hard => &self.hard,
+        }
+    }
+
+    fn get_mut(&mut self, difficulty: Difficulty) -> &mut Vec<String> {
+        match difficulty {
+            Difficulty::Easy => &mut self.easy,
+            Difficulty::Medium => &mut self.medium,
+            Difficulty::Hard => &mut self.hard,
+        }
+    }
+
+    pub fn num_of_words(&self) -> usize { self.easy.len() + self.medium.len() + self.hard.len() }
+}
+
+/// A named collection of words, loaded from a toml file like
+///
+/// ```toml
+/// name = "animals"
+/// language = "en"
+/// categories = ["nature"]
+///
+/// [words]
+/// easy = ["cat", "dog"]
+/// medium = ["giraffe"]
+/// hard = ["platypus"]
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct WordPack {
+    pub name: String,
+    pub language: String,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    pub words: PackWords,
+}
+
+impl WordPack {
+    /// create a pack from a plain list of words, one per line, these have no known difficulty so
+    /// it is guessed from their length
+    pub fn from_word_list(name: &str, words: &str) -> Self {
+        let mut pack_words = PackWords::default();
+        for word in words.lines().map(str::trim).filter(|x| !x.is_empty()) {
+            pack_words
+                .get_mut(Difficulty::of_word(word))
+                .push(word.to_string());
+        }
+
+        Self {
+            name: name.to_owned(),
+            language: "en".to_owned(),
+            categories: Vec::new(),
+            words: pack_words,
+        }
+    }
+
+    pub fn info(&self) -> WordPackInfo {
+        WordPackInfo {
+            name: self.name.clone(),
+            language: self.language.clone(),
+            categories: self.categories.clone(),
+            num_of_words: self.words.num_of_words(),
+        }
+    }
+}
+
+/// load every `.toml` word pack in the given directory, packs are chosen by name so no two may
+/// share one, nor take the name of the default pack
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> std::result::Result<Vec<WordPack>, String> {
+    let mut packs = Vec::new();
+    let mut names = HashMap::new();
+
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            let pack: WordPack = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| toml::from_str(&content).map_err(|e| e.to_string()))
+                .map_err(|e| format!("invalid word pack {}: {}", path.display(), e))?;
+
+            if pack.name == DEFAULT_PACK_NAME {
+                return Err(format!(
+                    "word pack {} cannot be named `{}`, that is the name of the default pack",
+                    path.display(),
+                    DEFAULT_PACK_NAME
+                ));
+            }
+
+            if let Some(other) = names.insert(pack.name.clone(), path.clone()) {
+                return Err(format!(
+                    "word packs {} and {} are both named `{}`",
+                    other.display(),
+                    path.display(),
+                    pack.name
+                ));
+            }
+
+            packs.push(pack);
+        }
+    }
+
+    Ok(packs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_pack(dir: &Path, file: &str, name: &str) {
+        let pack = format!(
+            "name = \"{}\"\nlanguage = \"en\"\n\n[words]\neasy = [\"cat\"]\n",
+            name
+        );
+        fs::write(dir.join(file), pack).unwrap();
+    }
+
+    #[test]
+    fn words_of_a_list_are_split_by_length() {
+        let pack = WordPack::from_word_list("list", "cat\n\n giraffe \nplatypus-bear\n");
+
+        assert_eq!(pack.words.easy, vec!["cat"]);
+        assert_eq!(pack.words.medium, vec!["giraffe"]);
+        assert_eq!(pack.words.hard, vec!["platypus-bear"]);
+    }
+
+    #[test]
+    fn packs_with_the_same_name_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        write_pack(dir.path(), "animals.toml", "animals");
+        write_pack(dir.path(), "food.toml", "food");
+        assert_eq!(load_dir(dir.path()).unwrap().len(), 2);
+
+        write_pack(dir.path(), "more_animals.toml", "animals");
+        assert!(load_dir(dir.path()).is_err());
+    }
+
+    #[test]
+    fn packs_cannot_take_the_default_name() {
+        let dir = tempfile::tempdir().unwrap();
+        write_pack(dir.path(), "default.toml", DEFAULT_PACK_NAME);
+
+        assert!(load_dir(dir.path()).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use futures_util::future::{AbortHandle, Abortable, Aborted};
 use std::future::Future;
 use tokio::{
-    io::{ReadHalf, WriteHalf},
+    io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf},
     net::TcpStream,
     task::JoinHandle,
 };
@@ -35,13 +35,19 @@ where
     AbortableTask(abort_handle, join_handle)
 }
 
-pub type MessageWriter<T> = FramedWrite<WriteHalf<TcpStream>, NetworkMessage<T>>;
-pub type MessageReader<T> = FramedRead<ReadHalf<TcpStream>, NetworkMessage<T>>;
+/// a connection messages are framed on, a tcp stream or a websocket
+pub trait Transport: AsyncRead + AsyncWrite + Send + 'static {}
 
-pub fn frame_socket<R, W>(st: TcpStream) -> (MessageReader<R>, MessageWriter<W>)
+impl<T: AsyncRead + AsyncWrite + Send + 'static> Transport for T {}
+
+pub type MessageWriter<T, S = TcpStream> = FramedWrite<WriteHalf<S>, NetworkMessage<T>>;
+pub type MessageReader<T, S = TcpStream> = FramedRead<ReadHalf<S>, NetworkMessage<T>>;
+
+pub fn frame_socket<R, W, S>(st: S) -> (MessageReader<R, S>, MessageWriter<W, S>)
 where
     for<'de> R: serde::Deserialize<'de>,
     W: serde::Serialize,
+    S: Transport,
 {
     let (r, w) = tokio::io::split(st);
     // let (r, w) = socket.into_split();
@@ -0,0 +1,322 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{ready, sink::Sink, stream::Stream};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{
+    tungstenite::{self, protocol::WebSocketConfig, Message},
+    WebSocketStream,
+};
+
+/// largest websocket message sent or accepted, longer writes are split across messages so a
+/// peer never has to hold more than this of a message
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+pub type Result<T> = std::result::Result<T, tungstenite::Error>;
+
+fn config() -> WebSocketConfig {
+    WebSocketConfig {
+        max_send_queue: None,
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+    }
+}
+
+/// answer the opening handshake of a client
+pub async fn accept<S>(stream: S) -> Result<WebSocket<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let inner = tokio_tungstenite::accept_async_with_config(stream, Some(config())).await?;
+    Ok(WebSocket::new(inner))
+}
+
+/// open a websocket to `url` over a connected stream, the host and path are sent as the url has
+/// them
+pub async fn connect<S>(url: &str, stream: S) -> Result<WebSocket<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (inner, _) =
+        tokio_tungstenite::client_async_with_config(url, stream, Some(config())).await?;
+    Ok(WebSocket::new(inner))
+}
+
+fn into_io_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// A websocket connection read and written as a plain byte stream.
+///
+/// Every write is sent as a binary message and the payloads of received messages are read back
+/// to back, so the `NetworkMessage` frames of the tcp protocol are carried unchanged. Pings are
+/// answered and a close frame ends the stream.
+#[derive(Debug)]
+pub struct WebSocket<S> {
+    inner: WebSocketStream<S>,
+    /// payload of the last received message
+    payload: Vec<u8>,
+    /// how much of the payload was read already
+    read: usize,
+}
+
+impl<S> WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn new(inner: WebSocketStream<S>) -> Self {
+        Self {
+            inner,
+            payload: Vec::new(),
+            read: 0,
+        }
+    }
+}
+
+impl<S> AsyncRead for WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            let unread = &this.payload[this.read..];
+            if !unread.is_empty() {
+                let len = buf.len().min(unread.len());
+                buf[..len].copy_from_slice(&unread[..len]);
+                this.read += len;
+                return Poll::Ready(Ok(len));
+            }
+
+            match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(payload))) => {
+                    this.payload = payload;
+                    this.read = 0;
+                }
+                // the payload of text messages is read the same, clients should send binary
+                Some(Ok(Message::Text(payload))) => {
+                    this.payload = payload.into_bytes();
+                    this.read = 0;
+                }
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => {}
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(0)),
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for WebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = Pin::new(&mut self.get_mut().inner);
+        ready!(inner.as_mut().poll_ready(cx)).map_err(into_io_error)?;
+
+        let len = buf.len().min(MAX_MESSAGE_SIZE);
+        inner
+            .start_send(Message::Binary(buf[..len].to_vec()))
+            .map_err(into_io_error)?;
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        encoding::NetworkMessage,
+        message::{ChatMessage, Handshake, ToClient, ToServer},
+        utils,
+    };
+    use bytes::BytesMut;
+    use futures_util::{SinkExt, StreamExt};
+    use std::net::SocketAddr;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    async fn listen() -> (TcpListener, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        (listener, addr)
+    }
+
+    /// a websocket of the library, as browsers and other clients would open it
+    async fn library_client(addr: SocketAddr) -> WebSocketStream<TcpStream> {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let url = format!("ws://{}/", addr);
+        let (client, _) = tokio_tungstenite::client_async(url.as_str(), stream)
+            .await
+            .unwrap();
+        client
+    }
+
+    /// answers a handshake and sends chat messages back as disconnects, until the client closes
+    async fn echo_server(mut listener: TcpListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let socket = accept(stream).await.unwrap();
+        let (mut reader, mut writer) = utils::frame_socket::<ToServer, ToClient, _>(socket);
+
+        while let Some(msg) = reader.next().await {
+            let reply = match msg.unwrap() {
+                ToServer::Handshake(_) => ToClient::Handshake(Handshake::default()),
+                ToServer::Chat(ChatMessage::System(text)) => ToClient::Disconnect(text),
+                _ => panic!("unexpected message"),
+            };
+            writer.send(reply).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn carries_protocol_messages_of_a_library_client() {
+        let (listener, addr) = listen().await;
+        let server = tokio::spawn(echo_server(listener));
+        let mut client = library_client(addr).await;
+
+        // long enough to be split across messages
+        let text = "a".repeat(MAX_MESSAGE_SIZE * 2);
+        let mut sent = BytesMut::new();
+        for msg in [
+            ToServer::Handshake(Handshake::default()),
+            ToServer::Chat(ChatMessage::System(text.clone())),
+        ] {
+            NetworkMessage::new().encode(msg, &mut sent).unwrap();
+        }
+        for chunk in sent.chunks(MAX_MESSAGE_SIZE) {
+            client.send(Message::Binary(chunk.to_vec())).await.unwrap();
+        }
+        client.send(Message::Ping(b"ping".to_vec())).await.unwrap();
+
+        let mut decoder = NetworkMessage::<ToClient>::new();
+        let mut received = BytesMut::new();
+        let mut replies = Vec::new();
+        let mut pong = false;
+        while replies.len() < 2 || !pong {
+            match client.next().await.unwrap().unwrap() {
+                Message::Binary(payload) => {
+                    assert!(payload.len() <= MAX_MESSAGE_SIZE);
+                    received.extend_from_slice(&payload);
+                }
+                Message::Pong(payload) => pong = payload == b"ping",
+                msg => panic!("unexpected {:?}", msg),
+            }
+
+            while let Some(reply) = decoder.decode(&mut received).unwrap() {
+                replies.push(reply);
+            }
+        }
+
+        assert!(matches!(
+            &replies[0],
+            ToClient::Handshake(handshake) if handshake.incompatibility().is_none()
+        ));
+        assert!(matches!(&replies[1], ToClient::Disconnect(reply) if *reply == text));
+
+        client.close(None).await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)] // the error response of the handshake callback
+    async fn connects_to_a_library_server_on_the_path_of_the_url() {
+        let (mut listener, addr) = listen().await;
+
+        // echoes messages back, after checking the request
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let check_request = |request: &Request, response: Response| {
+                assert_eq!(request.uri().path(), "/termibbl");
+                assert_eq!(request.headers()["host"], "localhost:1234");
+                Ok(response)
+            };
+            let mut socket = tokio_tungstenite::accept_hdr_async(stream, check_request)
+                .await
+                .unwrap();
+
+            while let Some(Ok(msg)) = socket.next().await {
+                if msg.is_binary() {
+                    socket.send(msg).await.unwrap();
+                }
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let socket = connect("ws://localhost:1234/termibbl", stream)
+            .await
+            .unwrap();
+        let (mut reader, mut writer) = utils::frame_socket::<ToServer, ToServer, _>(socket);
+
+        writer.send(ToServer::Heartbeat).await.unwrap();
+        assert!(matches!(reader.next().await, Some(Ok(ToServer::Heartbeat))));
+
+        writer.close().await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_oversized_messages() {
+        let (mut listener, addr) = listen().await;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let socket = accept(stream).await.unwrap();
+            let (mut reader, _) = utils::frame_socket::<ToServer, ToClient, _>(socket);
+            reader.next().await
+        });
+
+        let mut client = library_client(addr).await;
+        let _ = client
+            .send(Message::Binary(vec![0; MAX_MESSAGE_SIZE + 1]))
+            .await;
+
+        assert!(matches!(server.await.unwrap(), Some(Err(_))));
+    }
+
+    #[tokio::test]
+    async fn refuses_plain_http_requests() {
+        let (mut listener, addr) = listen().await;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            accept(stream).await.map(|_| ())
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        tokio::io::AsyncWriteExt::write_all(
+            &mut stream,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+        assert!(server.await.unwrap().is_err());
+    }
+}